@@ -0,0 +1,54 @@
+//! Counts the heap allocations made parsing formats of different lengths. Compare the counts
+//! with and without the `smallvec` feature, which keeps short formats off the heap.
+//!
+//! Run with `cargo bench --bench allocations` and `cargo bench --bench allocations --features
+//! smallvec`.
+
+extern crate apache_logline;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use apache_logline::{LogFormat, CLF, COMBINED};
+
+const FORMATS: u32 = 100_000;
+
+/// The system allocator, counting every allocation.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn main() {
+    let storage = if cfg!(feature = "smallvec") { "smallvec" } else { "vec" };
+    for &(name, format) in &[("%h", "%h"), ("clf", CLF), ("combined", COMBINED)] {
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        for _ in 0..FORMATS {
+            black_box(LogFormat::parse(black_box(format)).unwrap());
+        }
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+        println!("{:<10} {:<10} {:>6.2} allocations/parse",
+                 storage,
+                 name,
+                 allocations as f64 / f64::from(FORMATS));
+    }
+}
@@ -0,0 +1,33 @@
+//! Compares parsing directives with and without the `Cow` wrapper.
+//!
+//! Run with `cargo bench --bench directive_ref`.
+
+extern crate apache_logline;
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use apache_logline::{directive_parser, directive_ref_parser};
+
+const ITERATIONS: u32 = 1_000_000;
+const INPUTS: [&[u8]; 4] = [b"%h", b"%>s", b"%{User-agent}i", b"%{%d/%b/%Y:%H:%M:%S %z}t"];
+
+fn bench<F: Fn(&[u8])>(name: &str, parse: F) {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        for input in INPUTS.iter() {
+            parse(black_box(input));
+        }
+    }
+    let per_iter = start.elapsed() / (ITERATIONS * INPUTS.len() as u32);
+    println!("{:<24} {:>8?}/directive", name, per_iter);
+}
+
+fn main() {
+    bench("directive_parser", |input| {
+        black_box(directive_parser(input));
+    });
+    bench("directive_ref_parser", |input| {
+        black_box(directive_ref_parser(input));
+    });
+}
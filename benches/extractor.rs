@@ -0,0 +1,35 @@
+//! Times extracting fields from many lines with one `Extractor`, whose regex is compiled once
+//! up front, against building an extractor for every line.
+//!
+//! Run with `cargo bench --bench extractor`.
+
+extern crate apache_logline;
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use apache_logline::{LogFormat, COMBINED};
+
+const LINES: u32 = 10_000;
+
+fn main() {
+    let format = LogFormat::parse(COMBINED).unwrap();
+    let line = format.sample_line();
+
+    let start = Instant::now();
+    let extractor = format.extractor().unwrap();
+    println!("{:<24} {:>10?}", "compile", start.elapsed());
+
+    let start = Instant::now();
+    for _ in 0..LINES {
+        assert!(black_box(extractor.parse_line(black_box(&line))).is_some());
+    }
+    println!("{:<24} {:>10?}/line", "parse_line", start.elapsed() / LINES);
+
+    let start = Instant::now();
+    for _ in 0..LINES / 100 {
+        let extractor = format.extractor().unwrap();
+        assert!(black_box(extractor.parse_line(black_box(&line))).is_some());
+    }
+    println!("{:<24} {:>10?}/line", "compile and parse_line", start.elapsed() / (LINES / 100));
+}
@@ -0,0 +1,352 @@
+//! A compact binary encoding of parsed formats.
+//!
+//! The encoding is a version byte followed by the compact form of each directive: a tag byte,
+//! which for directives which carry a string is followed by the string's length, as an unsigned
+//! LEB128 varint, and its UTF-8 bytes. Most directives take no argument, so most take a single
+//! byte. A status condition is written as a flag for whether it's negated, the number of
+//! statuses and each status, as varints, followed by the directive it applies to.
+//!
+//! Version 1 of the encoding wrote lengths as little-endian `u32`s instead, and is still decoded.
+
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::error;
+use std::fmt;
+use std::str;
+
+use directive::{Condition, Directive, PIDType, PortType, TimeUnit};
+
+/// The version of the encoding written by `encode`. Bump this whenever the tags change.
+const VERSION: u8 = 2;
+
+/// The first version of the encoding, with fixed-width string lengths.
+const VERSION_FIXED_LENGTHS: u8 = 1;
+
+/// An error encountered while decoding a binary format.
+#[derive(Debug, PartialEq)]
+pub enum DecodeError {
+    /// The data was written by an incompatible version of the encoding.
+    UnsupportedVersion(u8),
+    /// A tag byte which doesn't correspond to any directive.
+    UnknownTag(u8),
+    /// The data ended part way through a directive.
+    UnexpectedEnd,
+    /// A string which isn't valid UTF-8.
+    InvalidUtf8,
+    /// A string length which doesn't fit in a `u32`.
+    InvalidLength,
+    /// Bytes left over after a single directive, when decoding with `Directive::from_compact`.
+    TrailingData,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::UnsupportedVersion(v) => write!(f, "unsupported encoding version {}", v),
+            DecodeError::UnknownTag(t) => write!(f, "unknown directive tag {}", t),
+            DecodeError::UnexpectedEnd => write!(f, "unexpected end of data"),
+            DecodeError::InvalidUtf8 => write!(f, "invalid UTF-8 in string"),
+            DecodeError::InvalidLength => write!(f, "invalid string length"),
+            DecodeError::TrailingData => write!(f, "unexpected data after directive"),
+        }
+    }
+}
+
+impl error::Error for DecodeError {}
+
+fn tag(directive: &Directive) -> u8 {
+    use directive::Directive::*;
+    match *directive {
+        Literal(_) => 0,
+        ClientIP => 1,
+        PeerIP => 2,
+        LocalIP => 3,
+        ResSizeExcludingHeaders => 4,
+        ResSize => 5,
+        Cookie(_) => 6,
+        ReqTime => 7,
+        EnvVar(_) => 8,
+        Filename => 9,
+        Hostname => 10,
+        Protocol => 11,
+        ReqHeader(_) => 12,
+        KeepAlive => 13,
+        Logname => 14,
+        ErrID => 15,
+        Method => 16,
+        Note(_) => 17,
+        ResHeader(_) => 18,
+        Port(PortType::Canonical) => 19,
+        Port(PortType::Local) => 20,
+        Port(PortType::Remote) => 21,
+        PID(PIDType::PID) => 22,
+        PID(PIDType::TID) => 23,
+        PID(PIDType::HexTID) => 24,
+        Query => 25,
+        ReqFirstLine => 26,
+        ResHandler => 27,
+        Status => 28,
+        FinalStatus => 29,
+        ReqRecvTime => 30,
+        ReqServeTime => 31,
+        User => 32,
+        Path => 33,
+        ServerName => 34,
+        CanonicalServerName => 35,
+        ResStatus => 36,
+        SizeReceived => 37,
+        SizeSent => 38,
+        Size => 39,
+        ReqTrailer(_) => 40,
+        ResTrailer(_) => 41,
+        ReqRecvTimeFmt(_) => 42,
+        UnknownChar(_) => 43,
+        ReqServeTimeUnit(TimeUnit::Seconds) => 44,
+        ReqServeTimeUnit(TimeUnit::Milliseconds) => 45,
+        ReqServeTimeUnit(TimeUnit::Microseconds) => 46,
+        ReqServeTimeUnit(TimeUnit::Custom(_)) => 49,
+        SslVar(_) => 50,
+        UnknownConnectionChar(_) => 51,
+        Conditional(_) => 52,
+    }
+}
+
+fn argument<'d>(directive: &'d Directive) -> Option<Cow<'d, str>> {
+    use directive::Directive::*;
+    match *directive {
+        Literal(ref s) | Cookie(ref s) | EnvVar(ref s) | ReqHeader(ref s) | Note(ref s) |
+        ResHeader(ref s) | ReqRecvTimeFmt(ref s) | ReqTrailer(ref s) | ResTrailer(ref s) |
+        SslVar(ref s) => {
+            Some(Cow::Borrowed(s))
+        }
+        UnknownChar(c) | UnknownConnectionChar(c) => Some(Cow::Owned(c.to_string())),
+        ReqServeTimeUnit(TimeUnit::Custom(ref s)) => Some(Cow::Borrowed(s)),
+        _ => None,
+    }
+}
+
+/// Append `len` as an unsigned LEB128 varint: seven bits per byte, least significant first, with
+/// the high bit set on every byte but the last.
+fn push_length(bytes: &mut Vec<u8>, mut len: u32) {
+    while len >= 0x80 {
+        bytes.push(len as u8 | 0x80);
+        len >>= 7;
+    }
+    bytes.push(len as u8);
+}
+
+/// Append the compact form of `directive`, without a version byte.
+pub fn encode_directive(directive: &Directive, bytes: &mut Vec<u8>) {
+    bytes.push(tag(directive));
+    if let Directive::Conditional(ref condition) = *directive {
+        bytes.push(condition.negated as u8);
+        push_length(bytes, condition.codes.len() as u32);
+        for &code in &condition.codes {
+            push_length(bytes, u32::from(code));
+        }
+        encode_directive(&condition.directive, bytes);
+    } else if let Some(s) = argument(directive) {
+        push_length(bytes, s.len() as u32);
+        bytes.extend_from_slice(s.as_bytes());
+    }
+}
+
+/// Encode a sequence of directives.
+pub fn encode(directives: &[Directive]) -> Vec<u8> {
+    let mut bytes = vec![VERSION];
+    for directive in directives {
+        encode_directive(directive, &mut bytes);
+    }
+    bytes
+}
+
+/// Split `n` bytes off the front of `bytes`.
+fn take<'b>(bytes: &mut &'b [u8], n: usize) -> Result<&'b [u8], DecodeError> {
+    if bytes.len() < n {
+        return Err(DecodeError::UnexpectedEnd);
+    }
+    let (head, tail) = bytes.split_at(n);
+    *bytes = tail;
+    Ok(head)
+}
+
+fn take_char(bytes: &mut &[u8], version: u8) -> Result<char, DecodeError> {
+    let s = take_string(bytes, version)?;
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err(DecodeError::InvalidUtf8),
+    }
+}
+
+/// Read a length written by `push_length`.
+fn take_length(bytes: &mut &[u8]) -> Result<u32, DecodeError> {
+    let mut len = 0u32;
+    for shift in (0..32).step_by(7) {
+        let byte = take(bytes, 1)?[0];
+        // Only the low four bits of the fifth byte fit in a `u32`.
+        if shift == 28 && byte & 0xf0 != 0 {
+            return Err(DecodeError::InvalidLength);
+        }
+        len |= u32::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(len);
+        }
+    }
+    Err(DecodeError::InvalidLength)
+}
+
+fn take_string(bytes: &mut &[u8], version: u8) -> Result<Cow<'static, str>, DecodeError> {
+    let len = if version == VERSION_FIXED_LENGTHS {
+        let mut len = [0; 4];
+        len.copy_from_slice(take(bytes, 4)?);
+        u32::from_le_bytes(len)
+    } else {
+        take_length(bytes)?
+    };
+    let s = take(bytes, len as usize)?;
+    str::from_utf8(s).map(|s| Cow::Owned(s.to_owned())).map_err(|_| DecodeError::InvalidUtf8)
+}
+
+/// Read a status condition written by `encode_directive`, after its tag.
+fn take_condition(bytes: &mut &[u8], version: u8) -> Result<Condition<'static>, DecodeError> {
+    let negated = take(bytes, 1)?[0] != 0;
+    let count = take_length(bytes)?;
+    let codes = (0..count)
+        .map(|_| take_length(bytes).and_then(|code| {
+            u16::try_from(code).map_err(|_| DecodeError::InvalidLength)
+        }))
+        .collect::<Result<Vec<_>, _>>()?;
+    // Conditions don't nest, so rejecting another one here also bounds the recursion.
+    if bytes.first() == Some(&52) {
+        return Err(DecodeError::UnknownTag(52));
+    }
+    let directive = decode_directive(bytes, version)?;
+    Ok(Condition { codes, negated, directive })
+}
+
+/// Decode one directive written by `encode_directive` from the front of `bytes`, with string
+/// lengths as written by `version` of the encoding.
+fn decode_directive(bytes: &mut &[u8], version: u8) -> Result<Directive<'static>, DecodeError> {
+    use directive::Directive::*;
+    Ok(match take(bytes, 1)?[0] {
+        0 => Literal(take_string(bytes, version)?),
+        1 => ClientIP,
+        2 => PeerIP,
+        3 => LocalIP,
+        4 => ResSizeExcludingHeaders,
+        5 => ResSize,
+        6 => Cookie(take_string(bytes, version)?),
+        7 => ReqTime,
+        8 => EnvVar(take_string(bytes, version)?),
+        9 => Filename,
+        10 => Hostname,
+        11 => Protocol,
+        12 => ReqHeader(take_string(bytes, version)?),
+        13 => KeepAlive,
+        14 => Logname,
+        15 => ErrID,
+        16 => Method,
+        17 => Note(take_string(bytes, version)?),
+        18 => ResHeader(take_string(bytes, version)?),
+        19 => Port(PortType::Canonical),
+        20 => Port(PortType::Local),
+        21 => Port(PortType::Remote),
+        22 => PID(PIDType::PID),
+        23 => PID(PIDType::TID),
+        24 => PID(PIDType::HexTID),
+        25 => Query,
+        26 => ReqFirstLine,
+        27 => ResHandler,
+        28 => Status,
+        29 => FinalStatus,
+        30 => ReqRecvTime,
+        31 => ReqServeTime,
+        32 => User,
+        33 => Path,
+        34 => ServerName,
+        35 => CanonicalServerName,
+        36 => ResStatus,
+        37 => SizeReceived,
+        38 => SizeSent,
+        39 => Size,
+        40 => ReqTrailer(take_string(bytes, version)?),
+        41 => ResTrailer(take_string(bytes, version)?),
+        42 => ReqRecvTimeFmt(take_string(bytes, version)?),
+        43 => UnknownChar(take_char(bytes, version)?),
+        44 => ReqServeTimeUnit(TimeUnit::Seconds),
+        45 => ReqServeTimeUnit(TimeUnit::Milliseconds),
+        46 => ReqServeTimeUnit(TimeUnit::Microseconds),
+        49 => ReqServeTimeUnit(TimeUnit::Custom(take_string(bytes, version)?)),
+        50 => SslVar(take_string(bytes, version)?),
+        51 => UnknownConnectionChar(take_char(bytes, version)?),
+        52 => Conditional(Box::new(take_condition(bytes, version)?)),
+        t => return Err(DecodeError::UnknownTag(t)),
+    })
+}
+
+/// Decode a single directive written by `encode_directive`, which must make up all of `bytes`.
+pub fn decode_compact(mut bytes: &[u8]) -> Result<Directive<'static>, DecodeError> {
+    let directive = decode_directive(&mut bytes, VERSION)?;
+    if !bytes.is_empty() {
+        return Err(DecodeError::TrailingData);
+    }
+    Ok(directive)
+}
+
+/// Decode a sequence of directives written by `encode`, or by version 1 of the encoding.
+pub fn decode(mut bytes: &[u8]) -> Result<Vec<Directive<'static>>, DecodeError> {
+    let version = match take(&mut bytes, 1)?[0] {
+        v @ VERSION | v @ VERSION_FIXED_LENGTHS => v,
+        v => return Err(DecodeError::UnsupportedVersion(v)),
+    };
+    let mut directives = Vec::new();
+    while !bytes.is_empty() {
+        directives.push(decode_directive(&mut bytes, version)?);
+    }
+    Ok(directives)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(encode(&[Directive::Hostname, Directive::Literal(Cow::from(" "))]),
+                   vec![VERSION, 10, 0, 1, b' ']);
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        assert_eq!(decode(&[]), Err(DecodeError::UnexpectedEnd));
+        assert_eq!(decode(&[VERSION + 1]), Err(DecodeError::UnsupportedVersion(VERSION + 1)));
+        assert_eq!(decode(&[VERSION, 200]), Err(DecodeError::UnknownTag(200)));
+        assert_eq!(decode(&[VERSION, 0, 5, b'a']), Err(DecodeError::UnexpectedEnd));
+        assert_eq!(decode(&[VERSION, 0, 0x80]), Err(DecodeError::UnexpectedEnd));
+        assert_eq!(decode(&[VERSION, 0, 0xff, 0xff, 0xff, 0xff, 0xff]),
+                   Err(DecodeError::InvalidLength));
+        assert_eq!(decode(&[VERSION, 0, 1, 0xff]), Err(DecodeError::InvalidUtf8));
+    }
+
+    #[test]
+    fn test_decode_fixed_length_version() {
+        let bytes = [VERSION_FIXED_LENGTHS, 10, 0, 1, 0, 0, 0, b' ', 12, 3, 0, 0, 0, b'F', b'o',
+                     b'o'];
+        assert_eq!(decode(&bytes),
+                   Ok(vec![Directive::Hostname,
+                           Directive::Literal(Cow::from(" ")),
+                           Directive::ReqHeader(Cow::from("Foo"))]));
+    }
+
+    #[test]
+    fn test_length_round_trip() {
+        for &len in &[0, 1, 0x7f, 0x80, 0x3fff, 0x4000, u32::MAX] {
+            let mut bytes = Vec::new();
+            push_length(&mut bytes, len);
+            let mut rest = &bytes[..];
+            assert_eq!(take_length(&mut rest), Ok(len));
+            assert!(rest.is_empty());
+        }
+    }
+}
@@ -0,0 +1,162 @@
+//! Reading and writing Apache httpd configuration directives.
+
+use std::borrow::Cow;
+
+/// Where a `CustomLog` directive writes its log.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LogTarget<'a> {
+    /// A file, relative to the `ServerRoot` unless absolute.
+    File(Cow<'a, str>),
+    /// A program the log is piped to, written with a leading `|`, which is removed.
+    Pipe(Cow<'a, str>),
+}
+
+/// The log format used by a `CustomLog` directive.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormatRef<'a> {
+    /// A format string given in the directive itself.
+    Inline(Cow<'a, str>),
+    /// The nickname of a format defined by a `LogFormat` directive, such as `combined`.
+    Nickname(Cow<'a, str>),
+}
+
+/// Split a configuration line into its arguments. Arguments are separated by whitespace, unless
+/// enclosed in double quotes, within which a backslash escapes a double quote or another
+/// backslash. As in Apache, any other backslash is kept, so that escapes such as the `\t` of a
+/// format string are left for the format parser to decode. Returns `None` if a quote is never
+/// closed.
+fn split_args(line: &str) -> Option<Vec<Cow<'_, str>>> {
+    let mut args = Vec::new();
+    let mut rest = line.trim_start();
+    while !rest.is_empty() {
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let mut arg = String::new();
+            let mut chars = quoted.char_indices().peekable();
+            let end = loop {
+                match chars.next()? {
+                    (_, '\\') if matches!(chars.peek(), Some(&(_, '\\')) | Some(&(_, '"'))) => {
+                        arg.push(chars.next()?.1)
+                    }
+                    (i, '"') => break i,
+                    (_, c) => arg.push(c),
+                }
+            };
+            args.push(if arg.len() == end {
+                Cow::Borrowed(&quoted[..end])
+            } else {
+                Cow::Owned(arg)
+            });
+            rest = &quoted[end + 1..];
+        } else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            args.push(Cow::Borrowed(&rest[..end]));
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+    }
+    Some(args)
+}
+
+/// Parse a `CustomLog` directive line, such as `CustomLog logs/access.log combined`, into where
+/// it logs to and the format it uses. Returns `None` if the line isn't a valid `CustomLog`.
+///
+/// As Apache does, a format argument containing a `%` is taken as a format string, and any other
+/// as a nickname. An `env=` or `expr=` condition after the format is ignored.
+pub fn parse_customlog_line(line: &str) -> Option<(LogTarget<'_>, FormatRef<'_>)> {
+    let mut args = split_args(line)?.into_iter();
+    if !args.next()?.eq_ignore_ascii_case("CustomLog") {
+        return None;
+    }
+    let target = args.next()?;
+    let format = args.next()?;
+    if args.len() > 1 {
+        return None;
+    }
+    let target = match target {
+        Cow::Borrowed(t) if t.starts_with('|') => LogTarget::Pipe(Cow::Borrowed(&t[1..])),
+        Cow::Owned(ref t) if t.starts_with('|') => LogTarget::Pipe(Cow::Owned(t[1..].to_owned())),
+        t => LogTarget::File(t),
+    };
+    let format = if format.contains('%') {
+        FormatRef::Inline(format)
+    } else {
+        FormatRef::Nickname(format)
+    };
+    Some((target, format))
+}
+
+/// Quote `arg` as a single httpd configuration argument, escaping any backslashes and double
+/// quotes within it.
+pub fn quote(arg: &str) -> String {
+    let mut quoted = String::with_capacity(arg.len() + 2);
+    quoted.push('"');
+    for c in arg.chars() {
+        if c == '"' || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use directive::Directive;
+    use parser::parse_str;
+    use CLF;
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("%h %l"), r#""%h %l""#);
+        assert_eq!(quote(r#""%r" \n"#), r#""\"%r\" \\n""#);
+    }
+
+    #[test]
+    fn test_parse_customlog_line_nickname() {
+        assert_eq!(parse_customlog_line("CustomLog logs/access.log combined"),
+                   Some((LogTarget::File(Cow::from("logs/access.log")),
+                         FormatRef::Nickname(Cow::from("combined")))));
+        assert_eq!(parse_customlog_line("  customlog \"|/usr/bin/rotatelogs /var/log/%Y\" common \
+                                         env=!dontlog"),
+                   Some((LogTarget::Pipe(Cow::from("/usr/bin/rotatelogs /var/log/%Y")),
+                         FormatRef::Nickname(Cow::from("common")))));
+    }
+
+    #[test]
+    fn test_parse_customlog_line_inline() {
+        let line = r#"CustomLog logs/access.log "%h %l %u %t \"%r\" %>s %b""#;
+        assert_eq!(parse_customlog_line(line),
+                   Some((LogTarget::File(Cow::from("logs/access.log")),
+                         FormatRef::Inline(Cow::from(r#"%h %l %u %t "%r" %>s %b"#)))));
+        assert_eq!(parse_customlog_line(&format!("CustomLog /dev/stdout {}", quote(CLF))),
+                   Some((LogTarget::File(Cow::from("/dev/stdout")),
+                         FormatRef::Inline(Cow::from(CLF)))));
+    }
+
+    #[test]
+    fn test_parse_customlog_line_inline_escapes() {
+        let format = match parse_customlog_line(r#"CustomLog logs/a "%h\t%u""#) {
+            Some((_, FormatRef::Inline(format))) => format,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(format, r"%h\t%u");
+        assert_eq!(parse_str(&format),
+                   Ok(vec![Directive::Hostname,
+                           Directive::Literal(Cow::from("\t")),
+                           Directive::User]));
+        assert_eq!(parse_customlog_line(r#"CustomLog logs/a "a\\b\"%h""#),
+                   Some((LogTarget::File(Cow::from("logs/a")),
+                         FormatRef::Inline(Cow::from(r#"a\b"%h"#)))));
+    }
+
+    #[test]
+    fn test_parse_customlog_line_invalid() {
+        assert_eq!(parse_customlog_line("LogFormat \"%h\" short"), None);
+        assert_eq!(parse_customlog_line("CustomLog logs/access.log"), None);
+        assert_eq!(parse_customlog_line("CustomLog logs/access.log \"%h"), None);
+        assert_eq!(parse_customlog_line("CustomLog a b env=x extra"), None);
+    }
+}
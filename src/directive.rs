@@ -1,21 +1,122 @@
 use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
 use std::str::FromStr;
+use std::time::Duration;
 
-#[derive(Debug, PartialEq)]
+use binary::{self, DecodeError};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum PortType {
     Canonical,
     Local,
     Remote,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum PIDType {
     PID,
     TID,
     HexTID,
 }
 
-#[derive(Debug, PartialEq)]
+/// The unit a request duration is logged in.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TimeUnit<'a> {
+    Seconds,
+    Milliseconds,
+    Microseconds,
+    /// A unit Apache itself doesn't support, such as the `min` of `%{min}T`, which some forks
+    /// and modules add. Kept verbatim.
+    Custom(Cow<'a, str>),
+}
+
+impl<'a> TimeUnit<'a> {
+    /// The unit as written between the braces of `%{UNIT}T`.
+    pub fn as_str(&self) -> &str {
+        match *self {
+            TimeUnit::Seconds => "s",
+            TimeUnit::Milliseconds => "ms",
+            TimeUnit::Microseconds => "us",
+            TimeUnit::Custom(ref unit) => unit,
+        }
+    }
+
+    /// Convert into a unit which owns its name, if it's a `Custom` one.
+    pub fn into_owned(self) -> TimeUnit<'static> {
+        match self {
+            TimeUnit::Seconds => TimeUnit::Seconds,
+            TimeUnit::Milliseconds => TimeUnit::Milliseconds,
+            TimeUnit::Microseconds => TimeUnit::Microseconds,
+            TimeUnit::Custom(unit) => TimeUnit::Custom(Cow::Owned(unit.into_owned())),
+        }
+    }
+}
+
+/// Recognizes `s`, `ms` and `us` case-insensitively, and keeps any other unit as `Custom`.
+impl<'a> From<&'a str> for TimeUnit<'a> {
+    fn from(unit: &'a str) -> TimeUnit<'a> {
+        if unit.eq_ignore_ascii_case("s") {
+            TimeUnit::Seconds
+        } else if unit.eq_ignore_ascii_case("ms") {
+            TimeUnit::Milliseconds
+        } else if unit.eq_ignore_ascii_case("us") {
+            TimeUnit::Microseconds
+        } else {
+            TimeUnit::Custom(Cow::Borrowed(unit))
+        }
+    }
+}
+
+/// A known HTTP version, as logged by `%H`. See `Directive::parse_protocol`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HttpVersion {
+    Http10,
+    Http11,
+    Http2,
+    Http3,
+}
+
+/// Formats the version as `%H` logs it; Apache logs HTTP/2 as `HTTP/2.0`.
+impl fmt::Display for HttpVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            HttpVersion::Http10 => "HTTP/1.0",
+            HttpVersion::Http11 => "HTTP/1.1",
+            HttpVersion::Http2 => "HTTP/2.0",
+            HttpVersion::Http3 => "HTTP/3.0",
+        })
+    }
+}
+
+/// What a logged client host, such as `%h`, holds. See `Directive::classify_host_value`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HostValueKind {
+    Ipv4,
+    Ipv6,
+    Hostname,
+}
+
+/// What a directive logs a property of. See `Directive::kind`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DirectiveKind {
+    /// Literal text.
+    Literal,
+    /// The client connection, such as the remote address or keep-alive count.
+    Connection,
+    /// The request, such as its first line, headers or the time it was received.
+    Request,
+    /// The response, such as its status, headers or size, or the time taken to serve it.
+    Response,
+    /// The server, such as its name, process or environment.
+    Server,
+    /// A `Directive::UnknownChar` or `Directive::UnknownConnectionChar`.
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Directive<'a> {
     /// Literal string.
     Literal(Cow<'a, str>),
@@ -37,7 +138,7 @@ pub enum Directive<'a> {
     ReqTime,
     /// The contents of the environment variable.
     EnvVar(Cow<'a, str>),
-    /// Filename.
+    /// Filename of the file served, on the server's filesystem. Compare `Path`, the URL path.
     Filename,
     /// Remote hostname. Will log the IP address if
     /// [HostnameLookups](https://httpd.apache.org/docs/trunk/mod/core.html#hostnamelookups)
@@ -93,18 +194,24 @@ pub enum Directive<'a> {
     /// Time the request was received, in the format [18/Sep/2011:19:18:28 -0400]. The last number
     /// indicates the timezone offset from GMT
     ReqRecvTime,
-    // [TODO]: Time with format
+    /// The time, in the form given by format, which should be in an extended
+    /// [strftime(3)](http://man7.org/linux/man-pages/man3/strftime.3.html) format (potentially
+    /// localized). If the format starts with `begin:` (default) the time is taken at the
+    /// beginning of the request processing. If it starts with `end:` it is the time when the log
+    /// entry gets written, close to the end of the request processing.
+    ReqRecvTimeFmt(Cow<'a, str>),
     /// The time taken to serve the request, in seconds.
     ReqServeTime,
     /// The time taken to serve the request, in a time unit given by UNIT. Valid units are ms for
     /// milliseconds, us for microseconds, and s for seconds. Using s gives the same result as %T
     /// without any format; using us gives the same result as %D. Combining %T with a unit is
     /// available in 2.4.13 and later.
-    // [TODO]: Time with unit
+    ReqServeTimeUnit(TimeUnit<'a>),
     /// Remote user if the request was authenticated. May be bogus if return status (%s) is 401
     /// (unauthorized).
     User,
-    /// The URL path requested, not including any query string.
+    /// The URL path requested, not including any query string. Compare `Filename`, the file on
+    /// disk which the path was mapped to.
     Path,
     /// The canonical [ServerName](https://httpd.apache.org/docs/trunk/mod/core.html#servername) of the server serving the request.
     ServerName,
@@ -131,14 +238,762 @@ pub enum Directive<'a> {
     ReqTrailer(Cow<'a, str>),
     /// The contents of trailer line(s) in the response sent from the server.
     ResTrailer(Cow<'a, str>),
+    /// The contents of the SSL/TLS variable VARNAME, such as `SSL_PROTOCOL`, from
+    /// [mod_ssl](https://httpd.apache.org/docs/2.4/mod/mod_ssl.html#logformats).
+    SslVar(Cow<'a, str>),
+    /// A single character directive which isn't known, only produced when parsing leniently.
+    UnknownChar(char),
+    /// A `%{c}X` directive, logging `X` for the underlying connection, where `X` isn't known
+    /// with the `{c}` scope. Only produced when parsing leniently.
+    UnknownConnectionChar(char),
+    /// A directive which is only logged for some statuses, as in `%400,501{User-agent}i`, and
+    /// logs a `-` otherwise.
+    Conditional(Box<Condition<'a>>),
 }
 
-impl<'a> FromStr for Directive<'a> {
+/// The status condition of a `Directive::Conditional`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition<'a> {
+    /// The statuses listed in the condition.
+    pub codes: Vec<u16>,
+    /// Whether the condition starts with a `!`, as in `%!200,304{Referer}i`, so the directive is
+    /// logged for every status except `codes`.
+    pub negated: bool,
+    /// The directive logged when the condition holds. It isn't itself conditional.
+    pub directive: Directive<'a>,
+}
+
+impl<'a> Condition<'a> {
+    /// Whether the directive is logged for a response with `status`.
+    pub fn matches(&self, status: u16) -> bool {
+        self.codes.contains(&status) != self.negated
+    }
+}
+
+/// Scramble `seed` into a well mixed value, so consecutive seeds give unrelated examples. This is
+/// the finalizer of the SplitMix64 generator.
+fn mix(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+const EXAMPLE_METHODS: [&str; 5] = ["GET", "POST", "HEAD", "PUT", "DELETE"];
+const EXAMPLE_STATUSES: [u16; 10] = [200, 201, 204, 301, 302, 304, 400, 403, 404, 500];
+const EXAMPLE_USERS: [&str; 4] = ["frank", "alice", "bob", "-"];
+
+/// Build a field name from a prefix and a user supplied argument, such as a header name.
+///
+/// The argument is lowercased and anything other than an ASCII letter or digit becomes an
+/// underscore, so the result is usable as a column or regex group name.
+fn prefixed_name(prefix: &str, arg: &str) -> Cow<'static, str> {
+    let mut name = String::with_capacity(prefix.len() + arg.len());
+    name.push_str(prefix);
+    name.extend(arg.chars().map(|c| if c.is_ascii_alphanumeric() {
+        c.to_ascii_lowercase()
+    } else {
+        '_'
+    }));
+    Cow::Owned(name)
+}
+
+/// Metadata about a directive which doesn't take a free-form argument.
+#[derive(Debug, Clone, Copy)]
+struct Metadata {
+    syntax: &'static str,
+    field_name: &'static str,
+    kind: DirectiveKind,
+    numeric: bool,
+    module: Option<&'static str>,
+    description: &'static str,
+}
+
+/// Declare the directives which don't take a free-form argument, each once with all its
+/// metadata, and the patterns of those which do.
+///
+/// This generates `Directive::all_niladic` and the private `Directive::metadata`, which the
+/// accessors fall back on once they've handled the directives with an argument. `metadata`
+/// matches every variant, so the compiler catches a directive missing from the table.
+macro_rules! directive_table {
+    (
+        niladic {
+            $($variant:ident $(($arg:path))* =>
+                  $syntax:expr, $field:expr, $kind:ident, $numeric:expr, $module:expr,
+                  $description:expr;)*
+        }
+        with_argument { $($param:pat_param)|* }
+    ) => {
+        const NILADIC: &[Directive<'static>] = &[$(Directive::$variant $(($arg))*),*];
+
+        impl<'a> Directive<'a> {
+            /// Every directive which doesn't take a free-form argument, including each fixed
+            /// argument of those such as `%{local}p`, in table order.
+            pub fn all_niladic() -> &'static [Directive<'static>] {
+                NILADIC
+            }
+
+            /// The table entry for this directive, or `None` if it takes a free-form argument.
+            fn metadata(&self) -> Option<Metadata> {
+                use self::Directive::*;
+                match *self {
+                    $($variant $(($arg))* => Some(Metadata {
+                        syntax: $syntax,
+                        field_name: $field,
+                        kind: DirectiveKind::$kind,
+                        numeric: $numeric,
+                        module: $module,
+                        description: $description,
+                    }),)*
+                    $($param)|* => None,
+                }
+            }
+        }
+    };
+}
+
+// Columns: syntax, field name, kind, whether numeric, required module, description.
+directive_table! {
+    niladic {
+        ClientIP => "%a", "client_ip", Connection, false, None,
+            "client IP address of the request";
+        PeerIP => "%{c}a", "peer_ip", Connection, false, None,
+            "peer IP address of the connection";
+        LocalIP => "%A", "local_ip", Connection, false, None,
+            "local IP address";
+        ResSizeExcludingHeaders => "%B", "response_bytes", Response, true, None,
+            "size of the response body in bytes";
+        ResSize => "%b", "response_size", Response, true, None,
+            "size of the response body in bytes, '-' for none";
+        ReqTime => "%D", "request_duration_us", Response, true, None,
+            "time taken to serve the request, in microseconds";
+        Filename => "%f", "filename", Request, false, None,
+            "filesystem path of the file served";
+        Hostname => "%h", "hostname", Connection, false, None,
+            "remote hostname";
+        Protocol => "%H", "protocol", Request, false, None,
+            "request protocol";
+        KeepAlive => "%k", "keepalive", Connection, true, None,
+            "number of keepalive requests handled on the connection";
+        // Logs a dash unless mod_ident is loaded and IdentityCheck is on.
+        Logname => "%l", "logname", Connection, false, Some("mod_ident"),
+            "remote logname from identd";
+        ErrID => "%L", "error_log_id", Server, false, None,
+            "request log ID from the error log";
+        Method => "%m", "method", Request, false, None,
+            "request method";
+        Port(PortType::Canonical) => "%p", "canonical_port", Connection, true, None,
+            "canonical port of the server";
+        Port(PortType::Local) => "%{local}p", "local_port", Connection, true, None,
+            "local port of the server";
+        Port(PortType::Remote) => "%{remote}p", "remote_port", Connection, true, None,
+            "remote port of the client";
+        PID(PIDType::PID) => "%P", "pid", Server, true, None,
+            "process ID of the child serving the request";
+        PID(PIDType::TID) => "%{tid}P", "tid", Server, true, None,
+            "thread ID of the child serving the request";
+        PID(PIDType::HexTID) => "%{hextid}P", "hex_tid", Server, false, None,
+            "thread ID of the child serving the request, in hex";
+        Query => "%q", "query", Request, false, None,
+            "query string, including the leading '?'";
+        ReqFirstLine => "%r", "request_line", Request, false, None,
+            "first line of the request";
+        ResHandler => "%R", "response_handler", Response, false, None,
+            "handler generating the response";
+        Status => "%s", "original_status", Response, true, None,
+            "status of the original request";
+        FinalStatus => "%>s", "status", Response, true, None,
+            "final status of the request";
+        ReqRecvTime => "%t", "time", Request, false, None,
+            "time the request was received";
+        ReqServeTime => "%T", "request_duration_s", Response, true, None,
+            "time taken to serve the request, in seconds";
+        ReqServeTimeUnit(TimeUnit::Seconds) => "%{s}T", "request_duration_s", Response, true, None,
+            "time taken to serve the request, in seconds";
+        ReqServeTimeUnit(TimeUnit::Milliseconds) =>
+            "%{ms}T", "request_duration_ms", Response, true, None,
+            "time taken to serve the request, in milliseconds";
+        ReqServeTimeUnit(TimeUnit::Microseconds) =>
+            "%{us}T", "request_duration_us", Response, true, None,
+            "time taken to serve the request, in microseconds";
+        User => "%u", "user", Request, false, None,
+            "remote user, if the request was authenticated";
+        Path => "%U", "url_path", Request, false, None,
+            "URL path requested, without the query string";
+        ServerName => "%v", "server_name", Server, false, None,
+            "canonical server name";
+        CanonicalServerName => "%V", "canonical_server_name", Server, false, None,
+            "server name according to UseCanonicalName";
+        ResStatus => "%X", "connection_status", Connection, false, None,
+            "connection status when the response completed";
+        SizeReceived => "%I", "bytes_received", Connection, true, Some("mod_logio"),
+            "bytes received, including request and headers";
+        SizeSent => "%O", "bytes_sent", Response, true, Some("mod_logio"),
+            "bytes sent, including headers";
+        Size => "%S", "bytes_transferred", Connection, true, Some("mod_logio"),
+            "bytes transferred, received and sent";
+    }
+    with_argument {
+        Literal(_) | Cookie(_) | EnvVar(_) | ReqHeader(_) | Note(_) |
+        ResHeader(_) | ReqRecvTimeFmt(_) | ReqServeTimeUnit(TimeUnit::Custom(_)) |
+        ReqTrailer(_) | ResTrailer(_) | SslVar(_) | UnknownChar(_) | UnknownConnectionChar(_) |
+        Conditional(_)
+    }
+}
+
+/// The table entry for a directive which doesn't take a free-form argument.
+fn entry(directive: &Directive) -> Metadata {
+    directive.metadata().expect("directive with an argument missing from an accessor")
+}
+
+/// Literals and directive arguments common enough to be shared by `into_owned_interned`: the
+/// separators of the standard formats, and the headers and variables most often logged.
+const INTERNED: &[&str] = &[" ", "\"", " \"", "\" ", "\" \"", " [", "] ", ":", "Referer",
+                            "User-Agent", "User-agent", "Host", "X-Forwarded-For", "Cookie",
+                            "Content-Type", "Content-Length", "Accept", "Accept-Encoding",
+                            "Accept-Language", "Authorization", "Location", "Set-Cookie",
+                            "X-Request-ID", "UNIQUE_ID"];
+
+/// The field name for the mod_ssl variable `name`: `ssl_protocol` for `SSL_PROTOCOL`, as the
+/// variables already start with `SSL_`.
+fn ssl_field_name(name: &str) -> Cow<'static, str> {
+    match name.get(..4) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("SSL_") => prefixed_name("ssl_", &name[4..]),
+        _ => prefixed_name("ssl_", name),
+    }
+}
+
+impl<'a> Directive<'a> {
+    /// Convert into a directive which owns any borrowed strings, so it can outlive the format
+    /// string it was parsed from.
+    pub fn into_owned(self) -> Directive<'static> {
+        self.own_with(&|s| Cow::Owned(s.into_owned()))
+    }
+
+    /// Convert into a directive which can outlive the format string it was parsed from, as
+    /// `into_owned`, but borrowing common literals and arguments such as `Referer` from a shared
+    /// static pool rather than copying them.
+    ///
+    /// Useful when keeping many formats, which mostly repeat the same separators and headers.
+    pub fn into_owned_interned(self) -> Directive<'static> {
+        self.own_with(&|s| match INTERNED.iter().find(|&&interned| interned == s) {
+            Some(interned) => Cow::Borrowed(interned),
+            None => Cow::Owned(s.into_owned()),
+        })
+    }
+
+    /// Encode the directive in a compact binary form: a single byte for directives without an
+    /// argument, which is most of them, followed by the argument's length and bytes for the rest.
+    ///
+    /// Unlike `LogFormat::to_bytes` the result carries no version, so it's meant for holding
+    /// directives in memory rather than for storing them.
+    pub fn to_compact(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(1);
+        binary::encode_directive(self, &mut bytes);
+        bytes
+    }
+
+    /// Decode a directive encoded with `to_compact`.
+    pub fn from_compact(bytes: &[u8]) -> Result<Directive<'static>, DecodeError> {
+        binary::decode_compact(bytes)
+    }
+
+    /// Convert into a directive with a `'static` lifetime, converting any strings with `own`.
+    fn own_with<F>(self, own: &F) -> Directive<'static>
+        where F: Fn(Cow<'a, str>) -> Cow<'static, str>
+    {
+        use self::Directive::*;
+        match self {
+            Literal(s) => Literal(own(s)),
+            ClientIP => ClientIP,
+            PeerIP => PeerIP,
+            LocalIP => LocalIP,
+            ResSizeExcludingHeaders => ResSizeExcludingHeaders,
+            ResSize => ResSize,
+            Cookie(s) => Cookie(own(s)),
+            ReqTime => ReqTime,
+            EnvVar(s) => EnvVar(own(s)),
+            Filename => Filename,
+            Hostname => Hostname,
+            Protocol => Protocol,
+            ReqHeader(s) => ReqHeader(own(s)),
+            KeepAlive => KeepAlive,
+            Logname => Logname,
+            ErrID => ErrID,
+            Method => Method,
+            Note(s) => Note(own(s)),
+            ResHeader(s) => ResHeader(own(s)),
+            Port(p) => Port(p),
+            PID(p) => PID(p),
+            Query => Query,
+            ReqFirstLine => ReqFirstLine,
+            ResHandler => ResHandler,
+            Status => Status,
+            FinalStatus => FinalStatus,
+            ReqRecvTime => ReqRecvTime,
+            ReqRecvTimeFmt(s) => ReqRecvTimeFmt(own(s)),
+            ReqServeTime => ReqServeTime,
+            ReqServeTimeUnit(u) => ReqServeTimeUnit(u.into_owned()),
+            User => User,
+            Path => Path,
+            ServerName => ServerName,
+            CanonicalServerName => CanonicalServerName,
+            ResStatus => ResStatus,
+            SizeReceived => SizeReceived,
+            SizeSent => SizeSent,
+            Size => Size,
+            ReqTrailer(s) => ReqTrailer(own(s)),
+            ResTrailer(s) => ResTrailer(own(s)),
+            SslVar(s) => SslVar(own(s)),
+            UnknownChar(c) => UnknownChar(c),
+            UnknownConnectionChar(c) => UnknownConnectionChar(c),
+            Conditional(condition) => {
+                let Condition { codes, negated, directive } = *condition;
+                let directive = directive.own_with(own);
+                Conditional(Box::new(Condition { codes, negated, directive }))
+            }
+        }
+    }
+
+    /// The statuses a `Conditional` directive is logged for, and whether the condition is
+    /// negated, in which case it's logged for every other status instead. `None` for directives
+    /// without a condition.
+    pub fn condition_codes(&self) -> Option<(&[u16], bool)> {
+        match *self {
+            Directive::Conditional(ref condition) => Some((&condition.codes, condition.negated)),
+            _ => None,
+        }
+    }
+
+    /// The directive a `Conditional` directive logs when its condition holds, or this directive
+    /// itself if it has no condition.
+    pub fn unconditional(&self) -> &Directive<'a> {
+        match *self {
+            Directive::Conditional(ref condition) => &condition.directive,
+            _ => self,
+        }
+    }
+
+    /// A short, snake_case name for the value this directive logs, suitable for use as a column
+    /// or capture group name. Literals have no field name.
+    pub fn field_name(&self) -> Option<Cow<'static, str>> {
+        use self::Directive::*;
+        Some(match *self {
+            Literal(_) => return None,
+            Cookie(ref name) => prefixed_name("cookie_", name),
+            EnvVar(ref name) => prefixed_name("env_", name),
+            ReqHeader(ref name) => prefixed_name("req_header_", name),
+            Note(ref name) => prefixed_name("note_", name),
+            ResHeader(ref name) => prefixed_name("res_header_", name),
+            ReqRecvTimeFmt(ref format) => prefixed_name("time_", format),
+            ReqServeTimeUnit(TimeUnit::Custom(ref unit)) => {
+                prefixed_name("request_duration_", unit)
+            }
+            ReqTrailer(ref name) => prefixed_name("req_trailer_", name),
+            ResTrailer(ref name) => prefixed_name("res_trailer_", name),
+            SslVar(ref name) => ssl_field_name(name),
+            UnknownChar(c) => prefixed_name("unknown_", c.encode_utf8(&mut [0; 4])),
+            UnknownConnectionChar(c) => {
+                prefixed_name("unknown_connection_", c.encode_utf8(&mut [0; 4]))
+            }
+            Conditional(ref condition) => return condition.directive.field_name(),
+            _ => Cow::Borrowed(entry(self).field_name),
+        })
+    }
+
+    /// The `field_name` of the underlying value this directive logs, ignoring distinctions which
+    /// analytics usually don't care about: `%s` and `%>s` are both `status`, `%t` in any format
+    /// is `time`, and every request duration, whatever its unit, is `request_duration`.
+    pub fn base_field(&self) -> Option<Cow<'static, str>> {
+        use self::Directive::*;
+        match *self {
+            Status | FinalStatus => Some(Cow::Borrowed("status")),
+            ReqRecvTimeFmt(_) => Some(Cow::Borrowed("time")),
+            ReqTime | ReqServeTime | ReqServeTimeUnit(_) => {
+                Some(Cow::Borrowed("request_duration"))
+            }
+            Conditional(ref condition) => condition.directive.base_field(),
+            _ => self.field_name(),
+        }
+    }
+
+    /// A one-line, human readable description of the value this directive logs.
+    pub fn description(&self) -> &'static str {
+        use self::Directive::*;
+        match *self {
+            Literal(_) => "literal text",
+            Cookie(_) => {
+                "contents of a request cookie (only version 0 cookies are fully supported)"
+            }
+            EnvVar(_) => "contents of an environment variable",
+            ReqHeader(_) => "contents of a request header",
+            Note(_) => "contents of a note from another module",
+            ResHeader(_) => "contents of a response header",
+            ReqRecvTimeFmt(_) => "time the request was received",
+            ReqServeTimeUnit(TimeUnit::Custom(_)) => {
+                "time taken to serve the request, in a non-standard unit"
+            }
+            ReqTrailer(_) => "contents of a request trailer",
+            ResTrailer(_) => "contents of a response trailer",
+            SslVar(_) => "contents of an SSL/TLS variable",
+            UnknownChar(_) => "unknown directive",
+            UnknownConnectionChar(_) => "unknown connection directive",
+            Conditional(ref condition) => condition.directive.description(),
+            _ => entry(self).description,
+        }
+    }
+
+    /// A typical value logged by this directive, as it would appear in a log line. Literals are
+    /// their own example.
+    pub fn example_value(&self) -> Cow<'_, str> {
+        use self::Directive::*;
+        let value = match *self {
+            Literal(ref s) => return Cow::Borrowed(s),
+            ClientIP | Hostname => "127.0.0.1",
+            PeerIP => "10.0.0.1",
+            LocalIP => "192.168.0.10",
+            ResSizeExcludingHeaders | ResSize => "2326",
+            Cookie(_) => "c3a1f7e2",
+            ReqTime => "1042",
+            Filename => "/var/www/html/apache_pb.gif",
+            Protocol => "HTTP/1.0",
+            ReqHeader(ref name) if name.eq_ignore_ascii_case("Referer") => {
+                "http://www.example.com/start.html"
+            }
+            ReqHeader(ref name) if name.eq_ignore_ascii_case("User-Agent") => {
+                "Mozilla/4.08 [en] (Win98; I ;Nav)"
+            }
+            KeepAlive => "0",
+            Logname => "-",
+            ErrID => "WZqO8n8AAQEAAAfQxUcAAAAA",
+            Method => "GET",
+            Port(PortType::Canonical) | Port(PortType::Local) => "80",
+            Port(PortType::Remote) => "52614",
+            PID(PIDType::PID) => "2137",
+            PID(PIDType::TID) => "140213424244480",
+            PID(PIDType::HexTID) => "7f8576ffd700",
+            Query => "?page=2",
+            ReqFirstLine => "GET /apache_pb.gif HTTP/1.0",
+            ResHandler => "default-handler",
+            Status | FinalStatus => "200",
+            ReqRecvTime => "[10/Oct/2000:13:55:36 -0700]",
+            ReqRecvTimeFmt(_) => "2000-10-10T13:55:36-0700",
+            ReqServeTime | ReqServeTimeUnit(TimeUnit::Seconds) => "0",
+            ReqServeTimeUnit(TimeUnit::Milliseconds) => "1",
+            ReqServeTimeUnit(TimeUnit::Microseconds) => "1042",
+            ReqServeTimeUnit(TimeUnit::Custom(_)) => "0",
+            User => "frank",
+            Path => "/apache_pb.gif",
+            ServerName | CanonicalServerName => "www.example.com",
+            ResStatus => "+",
+            SizeReceived => "468",
+            SizeSent => "2611",
+            Size => "3079",
+            EnvVar(_) | ReqHeader(_) | Note(_) | ResHeader(_) | ReqTrailer(_) | ResTrailer(_) |
+            SslVar(_) => "value",
+            UnknownChar(_) | UnknownConnectionChar(_) => "-",
+            Conditional(ref condition) => return condition.directive.example_value(),
+        };
+        Cow::Borrowed(value)
+    }
+
+    /// A deterministic example value which varies with `seed`, for generating many distinct
+    /// sample lines. Seed 0 gives `example_value`.
+    ///
+    /// Addresses, ports, process IDs, methods, paths, statuses, sizes, durations and users vary;
+    /// other directives always give their `example_value`. The same seed gives a consistent
+    /// request across `%m`, `%U` and `%r`.
+    pub fn example_value_seeded(&self, seed: u64) -> String {
+        use self::Directive::*;
+        if seed == 0 {
+            return self.example_value().into_owned();
+        }
+        let h = mix(seed);
+        let ip = |net: u64| {
+            format!("{}.{}.{}.{}", net, (h >> 8) & 0xff, (h >> 16) & 0xff, 1 + (h >> 24) % 254)
+        };
+        let method = EXAMPLE_METHODS[(h % EXAMPLE_METHODS.len() as u64) as usize];
+        let path = format!("/page/{}.html", (h >> 32) % 1000);
+        match *self {
+            ClientIP | Hostname => ip(1 + h % 223),
+            PeerIP => ip(10),
+            LocalIP => ip(192),
+            ResSizeExcludingHeaders | ResSize | SizeSent => ((h >> 12) % 100_000).to_string(),
+            SizeReceived => (100 + (h >> 12) % 2000).to_string(),
+            Size => (100 + (h >> 12) % 102_000).to_string(),
+            ReqTime | ReqServeTimeUnit(TimeUnit::Microseconds) => {
+                ((h >> 20) % 5_000_000).to_string()
+            }
+            ReqServeTimeUnit(TimeUnit::Milliseconds) => ((h >> 20) % 5_000).to_string(),
+            ReqServeTime | ReqServeTimeUnit(TimeUnit::Seconds) => ((h >> 20) % 5).to_string(),
+            Method => method.to_owned(),
+            Path => path,
+            ReqFirstLine => format!("{} {} HTTP/1.1", method, path),
+            Port(PortType::Remote) => (1024 + (h >> 40) % 64_511).to_string(),
+            PID(PIDType::PID) => (1 + (h >> 40) % 32_767).to_string(),
+            Status | FinalStatus => {
+                EXAMPLE_STATUSES[((h >> 4) % EXAMPLE_STATUSES.len() as u64) as usize].to_string()
+            }
+            User => EXAMPLE_USERS[((h >> 48) % EXAMPLE_USERS.len() as u64) as usize].to_owned(),
+            _ => self.example_value().into_owned(),
+        }
+    }
+
+    /// Compare two directives by their `field_name`, for sorting fields alphabetically.
+    /// Literals, which have no field name, sort first.
+    pub fn cmp_by_field_name(&self, other: &Directive) -> Ordering {
+        self.field_name().cmp(&other.field_name())
+    }
+
+    /// Whether this directive may log credentials or other personal data.
+    ///
+    /// This is deliberately conservative, covering only the obvious cases: the authenticated
+    /// user, any cookie, the `Authorization`, `Proxy-Authorization` and `Cookie` request headers,
+    /// and the `Set-Cookie` response header. Header names are matched case-insensitively.
+    pub fn is_potentially_sensitive(&self) -> bool {
+        use self::Directive::*;
+        const REQUEST_HEADERS: [&str; 3] = ["Authorization", "Proxy-Authorization", "Cookie"];
+        match *self {
+            User | Cookie(_) => true,
+            ReqHeader(ref name) => REQUEST_HEADERS.iter().any(|h| h.eq_ignore_ascii_case(name)),
+            ResHeader(ref name) => name.eq_ignore_ascii_case("Set-Cookie"),
+            // The client certificate and its subject identify the user.
+            SslVar(ref name) => name.to_ascii_uppercase().starts_with("SSL_CLIENT_"),
+            Conditional(ref condition) => condition.directive.is_potentially_sensitive(),
+            _ => false,
+        }
+    }
+
+    /// What this directive logs a property of.
+    pub fn kind(&self) -> DirectiveKind {
+        use self::Directive::*;
+        match *self {
+            Literal(_) => DirectiveKind::Literal,
+            Cookie(_) | ReqHeader(_) | ReqRecvTimeFmt(_) | ReqTrailer(_) => DirectiveKind::Request,
+            ResHeader(_) | ReqServeTimeUnit(_) | ResTrailer(_) => DirectiveKind::Response,
+            EnvVar(_) | Note(_) => DirectiveKind::Server,
+            SslVar(_) => DirectiveKind::Connection,
+            UnknownChar(_) | UnknownConnectionChar(_) => DirectiveKind::Unknown,
+            Conditional(ref condition) => condition.directive.kind(),
+            _ => entry(self).kind,
+        }
+    }
+
+    /// The module which must be loaded for this directive to log anything useful, if it isn't
+    /// provided by the core server.
+    pub fn required_module(&self) -> Option<&'static str> {
+        self.unconditional().metadata().and_then(|metadata| metadata.module)
+    }
+
+    /// The `%{UNIT}T` directive logging the same request duration as this one, but in `unit`.
+    /// Returns `None` for other directives, and when either unit is a `Custom` one, as the
+    /// conversion between them isn't known.
+    pub fn with_time_unit(&self, unit: &TimeUnit) -> Option<Directive<'static>> {
+        match (self.timing_base_unit()?, unit) {
+            (TimeUnit::Custom(_), _) | (_, &TimeUnit::Custom(_)) => None,
+            (_, unit) => Some(Directive::ReqServeTimeUnit(unit.clone().into_owned())),
+        }
+    }
+
+    /// The unit a request duration directive logs in, or `None` for other directives.
+    pub fn timing_base_unit(&self) -> Option<TimeUnit<'_>> {
+        match *self {
+            Directive::ReqTime => Some(TimeUnit::Microseconds),
+            Directive::ReqServeTime => Some(TimeUnit::Seconds),
+            Directive::ReqServeTimeUnit(ref unit) => Some(unit.clone()),
+            _ => None,
+        }
+    }
+
+    /// Parse a logged request duration, in the directive's `timing_base_unit`. Returns `None`
+    /// for other directives, for `Custom` units, or if `raw` isn't a whole number.
+    pub fn parse_timing(&self, raw: &str) -> Option<Duration> {
+        let unit = self.timing_base_unit()?;
+        let value = raw.parse::<u64>().ok()?;
+        Some(match unit {
+            TimeUnit::Seconds => Duration::from_secs(value),
+            TimeUnit::Milliseconds => Duration::from_millis(value),
+            TimeUnit::Microseconds => Duration::from_micros(value),
+            TimeUnit::Custom(_) => return None,
+        })
+    }
+
+    /// Parse a logged `%H` protocol. `HTTP/2` and `HTTP/3` are accepted with or without the
+    /// `.0`. Returns `None` for any other protocol.
+    pub fn parse_protocol(raw: &str) -> Option<HttpVersion> {
+        match raw {
+            "HTTP/1.0" => Some(HttpVersion::Http10),
+            "HTTP/1.1" => Some(HttpVersion::Http11),
+            "HTTP/2" | "HTTP/2.0" => Some(HttpVersion::Http2),
+            "HTTP/3" | "HTTP/3.0" => Some(HttpVersion::Http3),
+            _ => None,
+        }
+    }
+
+    /// Whether a logged `%h` or `%a` value is an IPv4 address, an IPv6 address or a hostname.
+    /// `%h` logs a hostname only when `HostnameLookups` is on and the lookup succeeds.
+    pub fn classify_host_value(raw: &str) -> HostValueKind {
+        if raw.parse::<Ipv4Addr>().is_ok() {
+            HostValueKind::Ipv4
+        } else if raw.parse::<Ipv6Addr>().is_ok() {
+            HostValueKind::Ipv6
+        } else {
+            HostValueKind::Hostname
+        }
+    }
+
+    /// Whether the `<` and `>` modifiers, which choose between the original and final request
+    /// of an internal redirect (as in `%>s`), change what this directive logs.
+    ///
+    /// Literals, and directives which log a property of the connection or of the server process
+    /// rather than of the request, log the same value for both.
+    pub fn supports_redirect_phase(&self) -> bool {
+        use self::Directive::*;
+        !matches!(*self.unconditional(),
+                  Literal(_) | PeerIP | LocalIP | KeepAlive | Port(PortType::Local) |
+                  Port(PortType::Remote) | PID(_) | ResStatus | UnknownChar(_) |
+                  UnknownConnectionChar(_))
+    }
+
+    /// Whether the kind of value this directive logs depends on the server configuration, so a
+    /// schema can't give it a more specific type than text.
+    ///
+    /// Only `%h` does: it logs the client's hostname when `HostnameLookups` is on and the
+    /// lookup succeeds, and its IP address otherwise. See `classify_host_value`.
+    pub fn value_is_conditional(&self) -> bool {
+        matches!(*self, Directive::Hostname)
+    }
+
+    /// Whether the directive logs a decimal integer.
+    pub fn is_numeric(&self) -> bool {
+        match *self {
+            Directive::ReqServeTimeUnit(_) => true,
+            _ => self.metadata().is_some_and(|metadata| metadata.numeric),
+        }
+    }
+
+    /// Whether Apache logs a `-` when this directive has no value, as in the Common Log Format.
+    ///
+    /// The exceptions are `%B`, which logs `0` for an empty response, `%k` and the mod_logio
+    /// byte counts `%I`, `%O` and `%S`, which always log a count, and `%q`, which logs an empty
+    /// string when there is no query string.
+    pub fn clf_dash_on_empty(&self) -> bool {
+        use self::Directive::*;
+        !matches!(*self,
+                  Literal(_) | ResSizeExcludingHeaders | KeepAlive | SizeReceived | SizeSent |
+                  Size | Query)
+    }
+}
+
+/// A directive which borrows its arguments directly, without a `Cow`, so it can be `Copy`.
+///
+/// The variants are the same as those of `Directive`, which this converts into cheaply, except
+/// that there's no `Conditional`: its status codes can't be borrowed from the format string.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DirectiveRef<'a> {
+    Literal(&'a str),
+    ClientIP,
+    PeerIP,
+    LocalIP,
+    ResSizeExcludingHeaders,
+    ResSize,
+    Cookie(&'a str),
+    ReqTime,
+    EnvVar(&'a str),
+    Filename,
+    Hostname,
+    Protocol,
+    ReqHeader(&'a str),
+    KeepAlive,
+    Logname,
+    ErrID,
+    Method,
+    Note(&'a str),
+    ResHeader(&'a str),
+    Port(PortType),
+    PID(PIDType),
+    Query,
+    ReqFirstLine,
+    ResHandler,
+    Status,
+    FinalStatus,
+    ReqRecvTime,
+    ReqRecvTimeFmt(&'a str),
+    ReqServeTime,
+    /// The unit as written, which converts into a `TimeUnit`.
+    ReqServeTimeUnit(&'a str),
+    User,
+    Path,
+    ServerName,
+    CanonicalServerName,
+    ResStatus,
+    SizeReceived,
+    SizeSent,
+    Size,
+    ReqTrailer(&'a str),
+    ResTrailer(&'a str),
+    SslVar(&'a str),
+    UnknownChar(char),
+    UnknownConnectionChar(char),
+}
+
+impl<'a> From<DirectiveRef<'a>> for Directive<'a> {
+    fn from(directive: DirectiveRef<'a>) -> Directive<'a> {
+        use self::DirectiveRef::*;
+        match directive {
+            Literal(s) => Directive::Literal(Cow::Borrowed(s)),
+            ClientIP => Directive::ClientIP,
+            PeerIP => Directive::PeerIP,
+            LocalIP => Directive::LocalIP,
+            ResSizeExcludingHeaders => Directive::ResSizeExcludingHeaders,
+            ResSize => Directive::ResSize,
+            Cookie(s) => Directive::Cookie(Cow::Borrowed(s)),
+            ReqTime => Directive::ReqTime,
+            EnvVar(s) => Directive::EnvVar(Cow::Borrowed(s)),
+            Filename => Directive::Filename,
+            Hostname => Directive::Hostname,
+            Protocol => Directive::Protocol,
+            ReqHeader(s) => Directive::ReqHeader(Cow::Borrowed(s)),
+            KeepAlive => Directive::KeepAlive,
+            Logname => Directive::Logname,
+            ErrID => Directive::ErrID,
+            Method => Directive::Method,
+            Note(s) => Directive::Note(Cow::Borrowed(s)),
+            ResHeader(s) => Directive::ResHeader(Cow::Borrowed(s)),
+            Port(x) => Directive::Port(x),
+            PID(x) => Directive::PID(x),
+            Query => Directive::Query,
+            ReqFirstLine => Directive::ReqFirstLine,
+            ResHandler => Directive::ResHandler,
+            Status => Directive::Status,
+            FinalStatus => Directive::FinalStatus,
+            ReqRecvTime => Directive::ReqRecvTime,
+            ReqRecvTimeFmt(s) => Directive::ReqRecvTimeFmt(Cow::Borrowed(s)),
+            ReqServeTime => Directive::ReqServeTime,
+            ReqServeTimeUnit(x) => Directive::ReqServeTimeUnit(TimeUnit::from(x)),
+            User => Directive::User,
+            Path => Directive::Path,
+            ServerName => Directive::ServerName,
+            CanonicalServerName => Directive::CanonicalServerName,
+            ResStatus => Directive::ResStatus,
+            SizeReceived => Directive::SizeReceived,
+            SizeSent => Directive::SizeSent,
+            Size => Directive::Size,
+            ReqTrailer(s) => Directive::ReqTrailer(Cow::Borrowed(s)),
+            ResTrailer(s) => Directive::ResTrailer(Cow::Borrowed(s)),
+            SslVar(s) => Directive::SslVar(Cow::Borrowed(s)),
+            UnknownChar(x) => Directive::UnknownChar(x),
+            UnknownConnectionChar(x) => Directive::UnknownConnectionChar(x),
+        }
+    }
+}
+
+impl<'a> FromStr for DirectiveRef<'a> {
     type Err = &'static str;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        use self::Directive::*;
+        use self::DirectiveRef::*;
         let d = match s {
-            "%" => Literal(Cow::from("%")),
+            "%" => Literal("%"),
             "a" => ClientIP,
             // {c}a => Underlying IP
             "A" => LocalIP,
@@ -165,11 +1020,11 @@ impl<'a> FromStr for Directive<'a> {
             "r" => ReqFirstLine,
             "R" => ResHandler,
             "s" => Status,
-            // %>s => FinalStatus
+            ">s" => FinalStatus,
             "t" => ReqRecvTime,
             // %{format}t => Time with format
             "T" => ReqServeTime,
-            // %{UNIT}t => Time with unit
+            // %{UNIT}T => Time with unit
             "u" => User,
             "U" => Path,
             "v" => ServerName,
@@ -180,21 +1035,353 @@ impl<'a> FromStr for Directive<'a> {
             "S" => Size,
             // %{VARNAME}^ti => Request trailer line
             // %{VARNAME}^to => Response trailer line
+            // %{VARNAME}x => SSL variable
             _ => return Err("invalid char"),
         };
         Ok(d)
     }
 }
 
+impl<'a> FromStr for Directive<'a> {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DirectiveRef::from_str(s).map(Directive::from)
+    }
+}
+
+/// Spell `literal` as it's written in a format string, doubling `%` and escaping backslashes,
+/// newlines, carriage returns and tabs as Apache decodes them.
+fn escape_literal(literal: &str) -> String {
+    let mut escaped = String::with_capacity(literal.len());
+    for c in literal.chars() {
+        match c {
+            '%' => escaped.push_str("%%"),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Formats the directive as it would be written in a log format string.
+impl<'a> fmt::Display for Directive<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Directive::*;
+        match *self {
+            Literal(ref s) => f.write_str(&escape_literal(s)),
+            Cookie(ref name) => write!(f, "%{{{}}}C", name),
+            EnvVar(ref name) => write!(f, "%{{{}}}e", name),
+            ReqHeader(ref name) => write!(f, "%{{{}}}i", name),
+            Note(ref name) => write!(f, "%{{{}}}n", name),
+            ResHeader(ref name) => write!(f, "%{{{}}}o", name),
+            ReqRecvTimeFmt(ref format) => write!(f, "%{{{}}}t", format),
+            ReqServeTimeUnit(TimeUnit::Custom(ref unit)) => write!(f, "%{{{}}}T", unit),
+            ReqTrailer(ref name) => write!(f, "%{{{}}}^ti", name),
+            ResTrailer(ref name) => write!(f, "%{{{}}}^to", name),
+            SslVar(ref name) => write!(f, "%{{{}}}x", name),
+            UnknownChar(c) => write!(f, "%{}", c),
+            UnknownConnectionChar(c) => write!(f, "%{{c}}{}", c),
+            Conditional(ref condition) => {
+                f.write_str(if condition.negated { "%!" } else { "%" })?;
+                for (i, code) in condition.codes.iter().enumerate() {
+                    write!(f, "{}{}", if i == 0 { "" } else { "," }, code)?;
+                }
+                f.write_str(&condition.directive.to_string()[1..])
+            }
+            _ => f.write_str(entry(self).syntax),
+        }
+    }
+}
+
+impl<'a> From<Directive<'a>> for String {
+    fn from(directive: Directive<'a>) -> String {
+        directive.to_string()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use parser::parse_str;
+
     #[test]
     fn test_directive_from_str() {
         assert_eq!(Directive::ClientIP, Directive::from_str("a").unwrap());
     }
 
+    #[test]
+    fn test_all_niladic() {
+        let all = Directive::all_niladic();
+        assert_eq!(all.len(), 37);
+        for directive in all {
+            let syntax = directive.to_string();
+            assert_eq!(parse_str(&syntax), Ok(vec![directive.clone()]), "{}", syntax);
+            let field_name = directive.field_name().unwrap();
+            assert!(field_name.bytes().all(|b| b.is_ascii_lowercase() || b == b'_'),
+                    "{}",
+                    field_name);
+            assert!(!directive.description().is_empty());
+            assert!(!matches!(directive.kind(), DirectiveKind::Literal | DirectiveKind::Unknown));
+            if directive.is_numeric() {
+                assert!(directive.example_value().bytes().all(|b| b.is_ascii_digit()),
+                        "{}",
+                        syntax);
+            }
+            assert!(directive.required_module().is_none_or(|m| m.starts_with("mod_")));
+        }
+    }
+
+    #[test]
+    fn test_directive_compact_round_trip() {
+        for directive in Directive::all_niladic() {
+            let bytes = directive.to_compact();
+            assert_eq!(bytes.len(), 1, "{}", directive);
+            assert_eq!(Directive::from_compact(&bytes).as_ref(), Ok(directive));
+        }
+        let header = Directive::ReqHeader(Cow::from("Referer"));
+        let bytes = header.to_compact();
+        assert_eq!(bytes.len(), 2 + "Referer".len());
+        assert_eq!(Directive::from_compact(&bytes), Ok(header));
+        assert_eq!(Directive::from_compact(&[]), Err(DecodeError::UnexpectedEnd));
+        assert_eq!(Directive::from_compact(&[10, 10]), Err(DecodeError::TrailingData));
+        let conditional = parse_str("%!200,304{Referer}i").unwrap().remove(0);
+        assert_eq!(Directive::from_compact(&conditional.to_compact()), Ok(conditional));
+        assert_eq!(Directive::from_compact(&[52, 0, 1, 200, 1, 52, 0, 0, 10]),
+                   Err(DecodeError::UnknownTag(52)));
+    }
+
+    #[test]
+    fn test_directive_conditional() {
+        let directive = parse_str("%400,501{User-agent}i").unwrap().remove(0);
+        assert_eq!(directive.condition_codes(), Some((&[400, 501][..], false)));
+        assert_eq!(directive.field_name(), Some(Cow::from("req_header_user_agent")));
+        assert_eq!(directive.kind(), DirectiveKind::Request);
+        assert_eq!(directive.clone().into_owned(), directive);
+        match directive {
+            Directive::Conditional(ref condition) => {
+                assert!(condition.matches(400));
+                assert!(!condition.matches(200));
+            }
+            _ => panic!("{:?}", directive),
+        }
+        let negated = parse_str("%!200{Referer}i").unwrap().remove(0);
+        assert_eq!(negated.condition_codes(), Some((&[200][..], true)));
+        assert_eq!(negated.to_string(), "%!200{Referer}i");
+        assert_eq!(Directive::ReqHeader(Cow::from("Referer")).condition_codes(), None);
+    }
+
+    #[test]
+    fn test_directive_field_name() {
+        assert_eq!(Directive::Hostname.field_name(), Some(Cow::from("hostname")));
+        assert_eq!(Directive::ReqHeader(Cow::from("User-Agent")).field_name(),
+                   Some(Cow::from("req_header_user_agent")));
+        assert_eq!(Directive::Literal(Cow::from(" ")).field_name(), None);
+    }
+
+    #[test]
+    fn test_directive_field_name_note_and_env_var() {
+        let format = parse_str("%{foo}n %{foo}e").unwrap();
+        let names: Vec<_> = format.iter().filter_map(Directive::field_name).collect();
+        assert_eq!(names, ["note_foo", "env_foo"]);
+    }
+
+    #[test]
+    fn test_directive_filename_and_path() {
+        // `%f` is the file on disk, `%U` the URL path which was mapped to it.
+        assert_eq!(Directive::Filename.field_name(), Some(Cow::from("filename")));
+        assert_eq!(Directive::Path.field_name(), Some(Cow::from("url_path")));
+        assert!(Directive::Filename.description().contains("filesystem"));
+        assert!(Directive::Path.description().contains("URL"));
+    }
+
+    #[test]
+    fn test_directive_kind() {
+        assert_eq!(Directive::Literal(Cow::from(" ")).kind(), DirectiveKind::Literal);
+        assert_eq!(Directive::Hostname.kind(), DirectiveKind::Connection);
+        assert_eq!(Directive::ReqFirstLine.kind(), DirectiveKind::Request);
+        assert_eq!(Directive::FinalStatus.kind(), DirectiveKind::Response);
+        assert_eq!(Directive::ServerName.kind(), DirectiveKind::Server);
+        assert_eq!(Directive::UnknownChar('z').kind(), DirectiveKind::Unknown);
+        assert_eq!(Directive::UnknownConnectionChar('z').kind(), DirectiveKind::Unknown);
+    }
+
+    #[test]
+    fn test_directive_base_field() {
+        assert_eq!(Directive::Status.base_field(), Some(Cow::from("status")));
+        assert_eq!(Directive::FinalStatus.base_field(), Some(Cow::from("status")));
+        assert_eq!(Directive::ReqServeTimeUnit(TimeUnit::Milliseconds).base_field(),
+                   Directive::ReqTime.base_field());
+        assert_eq!(Directive::Hostname.base_field(), Directive::Hostname.field_name());
+        let msec = Directive::ReqRecvTimeFmt(Cow::from("begin:msec"));
+        assert_eq!(msec.field_name(), Some(Cow::from("time_begin_msec")));
+        assert_eq!(msec.base_field(), Directive::ReqRecvTime.base_field());
+        assert_eq!(Directive::Literal(Cow::from(" ")).base_field(), None);
+    }
+
+    #[test]
+    fn test_directive_keepalive_is_numeric() {
+        assert!(Directive::KeepAlive.is_numeric());
+        assert!(!Directive::KeepAlive.clf_dash_on_empty());
+        assert!(!Directive::PID(PIDType::HexTID).is_numeric());
+        assert!(Directive::User.clf_dash_on_empty());
+    }
+
+    #[test]
+    fn test_directive_sizes() {
+        assert_eq!(Directive::ResSize.field_name(), Some(Cow::from("response_size")));
+        assert_eq!(Directive::SizeSent.field_name(), Some(Cow::from("bytes_sent")));
+        assert!(Directive::ResSize.clf_dash_on_empty());
+        assert!(!Directive::ResSizeExcludingHeaders.clf_dash_on_empty());
+        assert!(!Directive::SizeSent.clf_dash_on_empty());
+        assert!(!Directive::SizeReceived.clf_dash_on_empty());
+        assert!(!Directive::Size.clf_dash_on_empty());
+    }
+
+    #[test]
+    fn test_directive_parse_timing() {
+        assert_eq!(Directive::ReqTime.parse_timing("1500"), Some(Duration::from_micros(1500)));
+        assert_eq!(Directive::ReqServeTime.parse_timing("2"), Some(Duration::from_secs(2)));
+        assert_eq!(Directive::ReqServeTimeUnit(TimeUnit::Milliseconds).parse_timing("15"),
+                   Some(Duration::from_millis(15)));
+        assert_eq!(Directive::ReqTime.parse_timing("-"), None);
+        assert_eq!(Directive::Status.parse_timing("200"), None);
+    }
+
+    #[test]
+    fn test_directive_value_is_conditional() {
+        assert!(Directive::Hostname.value_is_conditional());
+        assert!(!Directive::ClientIP.value_is_conditional());
+        assert!(!Directive::PeerIP.value_is_conditional());
+        assert!(!Directive::Literal(Cow::from(" ")).value_is_conditional());
+    }
+
+    #[test]
+    fn test_directive_classify_host_value() {
+        assert_eq!(Directive::classify_host_value("127.0.0.1"), HostValueKind::Ipv4);
+        assert_eq!(Directive::classify_host_value("2001:db8::1"), HostValueKind::Ipv6);
+        assert_eq!(Directive::classify_host_value("::ffff:192.0.2.1"), HostValueKind::Ipv6);
+        assert_eq!(Directive::classify_host_value("www.example.com"), HostValueKind::Hostname);
+        assert_eq!(Directive::classify_host_value("10.0.0.256"), HostValueKind::Hostname);
+    }
+
+    #[test]
+    fn test_directive_with_time_unit() {
+        let ms = Directive::ReqServeTimeUnit(TimeUnit::Milliseconds);
+        assert_eq!(Directive::ReqTime.with_time_unit(&TimeUnit::Milliseconds), Some(ms.clone()));
+        assert_eq!(Directive::ReqServeTime.with_time_unit(&TimeUnit::Milliseconds), Some(ms));
+        assert_eq!(Directive::ReqTime.with_time_unit(&TimeUnit::Custom(Cow::from("min"))), None);
+        let min = Directive::ReqServeTimeUnit(TimeUnit::Custom(Cow::from("min")));
+        assert_eq!(min.with_time_unit(&TimeUnit::Seconds), None);
+        assert_eq!(Directive::Status.with_time_unit(&TimeUnit::Seconds), None);
+    }
+
+    #[test]
+    fn test_directive_parse_protocol() {
+        assert_eq!(Directive::parse_protocol("HTTP/1.0"), Some(HttpVersion::Http10));
+        assert_eq!(Directive::parse_protocol("HTTP/1.1"), Some(HttpVersion::Http11));
+        assert_eq!(Directive::parse_protocol("HTTP/2"), Some(HttpVersion::Http2));
+        assert_eq!(Directive::parse_protocol("HTTP/2.0"), Some(HttpVersion::Http2));
+        assert_eq!(Directive::parse_protocol("HTTP/3"), Some(HttpVersion::Http3));
+        assert_eq!(Directive::parse_protocol("HTTP/0.9"), None);
+        assert_eq!(Directive::parse_protocol("SPDY/3"), None);
+        assert_eq!(HttpVersion::Http2.to_string(), "HTTP/2.0");
+    }
+
+    #[test]
+    fn test_directive_ref_into_directive() {
+        let directive = DirectiveRef::ReqHeader("Referer");
+        let copy = directive;
+        assert_eq!(Directive::from(directive), Directive::ReqHeader(Cow::from("Referer")));
+        assert_eq!(Directive::from(copy), Directive::ReqHeader(Cow::from("Referer")));
+        assert_eq!(Directive::from(DirectiveRef::Port(PortType::Local)),
+                   Directive::Port(PortType::Local));
+    }
+
+    #[test]
+    fn test_directive_supports_redirect_phase() {
+        assert!(Directive::Status.supports_redirect_phase());
+        assert!(Directive::ReqHeader(Cow::from("Host")).supports_redirect_phase());
+        assert!(!Directive::Literal(Cow::from(" ")).supports_redirect_phase());
+        assert!(!Directive::PID(PIDType::PID).supports_redirect_phase());
+    }
+
+    #[test]
+    fn test_directive_required_module() {
+        assert_eq!(Directive::SizeReceived.required_module(), Some("mod_logio"));
+        assert_eq!(Directive::Hostname.required_module(), None);
+    }
+
+    #[test]
+    fn test_directive_is_potentially_sensitive() {
+        assert!(Directive::Cookie(Cow::from("session")).is_potentially_sensitive());
+        assert!(Directive::ReqHeader(Cow::from("authorization")).is_potentially_sensitive());
+        assert!(!Directive::ReqHeader(Cow::from("Referer")).is_potentially_sensitive());
+        assert!(!Directive::Hostname.is_potentially_sensitive());
+    }
+
+    #[test]
+    fn test_ssl_field_name() {
+        assert_eq!(Directive::SslVar(Cow::from("SSL_PROTOCOL")).field_name(),
+                   Some(Cow::from("ssl_protocol")));
+        assert_eq!(Directive::SslVar(Cow::from("ssl_client_s_dn_CN")).field_name(),
+                   Some(Cow::from("ssl_client_s_dn_cn")));
+        assert_eq!(Directive::SslVar(Cow::from("HTTPS")).field_name(),
+                   Some(Cow::from("ssl_https")));
+        assert!(Directive::SslVar(Cow::from("SSL_CLIENT_S_DN")).is_potentially_sensitive());
+        assert!(!Directive::SslVar(Cow::from("SSL_PROTOCOL")).is_potentially_sensitive());
+    }
+
+    #[test]
+    fn test_directive_display() {
+        assert_eq!(Directive::Hostname.to_string(), "%h");
+        assert_eq!(Directive::FinalStatus.to_string(), "%>s");
+        assert_eq!(Directive::ReqHeader(Cow::from("Referer")).to_string(), "%{Referer}i");
+        assert_eq!(Directive::ResTrailer(Cow::from("Expires")).to_string(), "%{Expires}^to");
+        assert_eq!(Directive::SslVar(Cow::from("SSL_PROTOCOL")).to_string(), "%{SSL_PROTOCOL}x");
+        assert_eq!(Directive::Literal(Cow::from("100% ")).to_string(), "100%% ");
+        assert_eq!(Directive::Literal(Cow::from("a\tb\\")).to_string(), r"a\tb\\");
+    }
+
+    #[test]
+    fn test_directive_example_value() {
+        assert_eq!(Directive::Hostname.example_value(), "127.0.0.1");
+        assert_eq!(Directive::Literal(Cow::from(" [")).example_value(), " [");
+        assert_eq!(Directive::ReqHeader(Cow::from("referer")).example_value(),
+                   "http://www.example.com/start.html");
+    }
+
+    #[test]
+    fn test_directive_example_value_seeded() {
+        assert_eq!(Directive::Hostname.example_value_seeded(0), "127.0.0.1");
+        assert_eq!(Directive::Hostname.example_value_seeded(1),
+                   Directive::Hostname.example_value_seeded(1));
+        assert_ne!(Directive::Hostname.example_value_seeded(1),
+                   Directive::Hostname.example_value_seeded(2));
+        for seed in 1..100 {
+            let line = Directive::ReqFirstLine.example_value_seeded(seed);
+            assert!(line.starts_with(&Directive::Method.example_value_seeded(seed)));
+            assert!(Directive::Hostname.example_value_seeded(seed).parse::<Ipv4Addr>().is_ok());
+            assert!(Directive::FinalStatus.example_value_seeded(seed).parse::<u16>().is_ok());
+        }
+    }
+
+    #[test]
+    fn test_directive_from_str_final_status() {
+        assert_eq!(">s".parse::<Directive>(), Ok(Directive::FinalStatus));
+        assert_eq!("s".parse::<Directive>(), Ok(Directive::Status));
+        assert!(">h".parse::<Directive>().is_err());
+    }
+
+    #[test]
+    fn test_directive_into_string() {
+        assert_eq!(String::from(Directive::Hostname), "%h");
+        assert_eq!(String::from(Directive::ReqHeader(Cow::from("Referer"))), "%{Referer}i");
+    }
+
     #[test]
     fn test_directive_from_str_percent() {
         assert_eq!(Directive::Literal(Cow::from("%")),
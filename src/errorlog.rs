@@ -0,0 +1,275 @@
+//! Parsing `ErrorLogFormat` strings, which describe Apache's error log.
+//!
+//! Error log formats use the same `%` syntax as access log formats, but a partly different set
+//! of directives. See the
+//! [ErrorLogFormat](https://httpd.apache.org/docs/2.4/mod/core.html#errorlogformat) docs.
+
+use std::borrow::Cow;
+use std::fmt;
+
+use directive::{Directive, PIDType};
+use parser::ParseError;
+
+/// A directive in an error log format.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ErrorLogDirective<'a> {
+    /// A directive which logs the same value as in an access log format: literal text,
+    /// `%{name}e`, `%{name}i`, `%k`, `%L`, `%{name}n`, `%P`, `%v` or `%V`.
+    Shared(Directive<'a>),
+    /// Client IP address and port of the request.
+    ClientAddress,
+    /// Underlying peer IP address and port of the connection (see the
+    /// [mod_remoteip](https://httpd.apache.org/docs/2.4/mod/mod_remoteip.html) module).
+    PeerAddress,
+    /// Local IP address and port.
+    LocalAddress,
+    /// APR/OS error status code and string.
+    AprStatus,
+    /// Source file name and line number of the log call.
+    SourceFile,
+    /// Log level of the message.
+    LogLevel,
+    /// Log ID of the connection.
+    ConnectionLogId,
+    /// Log ID of the connection if in connection scope, empty otherwise.
+    ConnectionScopeLogId,
+    /// Name of the module logging the message.
+    Module,
+    /// The actual log message.
+    Message,
+    /// Thread ID.
+    ThreadId,
+    /// System unique thread ID.
+    SystemThreadId,
+    /// The current time.
+    Time,
+    /// The current time including microseconds.
+    TimeMicros,
+    /// The current time in compact ISO 8601 format, including microseconds.
+    TimeCompact,
+    /// A field delimiter, written `% ` (percent space). Logged as a space.
+    FieldSeparator,
+}
+
+/// One item of an error log format: a directive and the modifiers written between its `%` and
+/// the directive character. Literals never have modifiers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorLogItem<'a> {
+    pub directive: ErrorLogDirective<'a>,
+    /// `%-`: log a `-` when the item is empty, rather than nothing.
+    pub hyphen_if_empty: bool,
+    /// `%+`: omit the entire line when the item is empty.
+    pub required: bool,
+    /// A number such as the `7` of `%7F`: only log the item at this log level (here `debug`) or
+    /// more verbose.
+    pub min_level: Option<u8>,
+}
+
+impl<'a> From<ErrorLogDirective<'a>> for ErrorLogItem<'a> {
+    fn from(directive: ErrorLogDirective<'a>) -> ErrorLogItem<'a> {
+        ErrorLogItem { directive, hyphen_if_empty: false, required: false, min_level: None }
+    }
+}
+
+/// Formats the item as it would be written in an error log format string.
+impl<'a> fmt::Display for ErrorLogItem<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::ErrorLogDirective::*;
+        // Error log formats don't decode backslash escapes, so only `%` is escaped.
+        if let Shared(Directive::Literal(ref s)) = self.directive {
+            return f.write_str(&s.replace('%', "%%"));
+        }
+        f.write_str("%")?;
+        if self.hyphen_if_empty {
+            f.write_str("-")?;
+        }
+        if self.required {
+            f.write_str("+")?;
+        }
+        if let Some(level) = self.min_level {
+            write!(f, "{}", level)?;
+        }
+        let s = match self.directive {
+            // Skip the `%` the directive is written with.
+            Shared(ref d) => return f.write_str(&d.to_string()[1..]),
+            ClientAddress => "a",
+            PeerAddress => "{c}a",
+            LocalAddress => "A",
+            AprStatus => "E",
+            SourceFile => "F",
+            LogLevel => "l",
+            ConnectionLogId => "{c}L",
+            ConnectionScopeLogId => "{C}L",
+            Module => "m",
+            Message => "M",
+            ThreadId => "T",
+            SystemThreadId => "{g}T",
+            Time => "t",
+            TimeMicros => "{u}t",
+            TimeCompact => "{cu}t",
+            FieldSeparator => " ",
+        };
+        f.write_str(s)
+    }
+}
+
+/// The directive written as `c`, with the brace argument `arg` if there is one.
+fn directive<'a>(c: char, arg: Option<&'a str>) -> Option<ErrorLogDirective<'a>> {
+    use self::ErrorLogDirective::*;
+    let name = || arg.filter(|name| !name.is_empty()).map(Cow::Borrowed);
+    Some(match (c, arg) {
+        (' ', None) => FieldSeparator,
+        ('a', None) => ClientAddress,
+        ('a', Some("c")) => PeerAddress,
+        ('A', None) => LocalAddress,
+        ('e', Some(_)) => Shared(Directive::EnvVar(name()?)),
+        ('E', None) => AprStatus,
+        ('F', None) => SourceFile,
+        ('i', Some(_)) => Shared(Directive::ReqHeader(name()?)),
+        ('k', None) => Shared(Directive::KeepAlive),
+        ('l', None) => LogLevel,
+        ('L', None) => Shared(Directive::ErrID),
+        ('L', Some("c")) => ConnectionLogId,
+        ('L', Some("C")) => ConnectionScopeLogId,
+        ('m', None) => Module,
+        ('M', None) => Message,
+        ('n', Some(_)) => Shared(Directive::Note(name()?)),
+        ('P', None) => Shared(Directive::PID(PIDType::PID)),
+        ('T', None) => ThreadId,
+        ('T', Some("g")) => SystemThreadId,
+        ('t', None) => Time,
+        ('t', Some("u")) => TimeMicros,
+        ('t', Some("cu")) => TimeCompact,
+        ('v', None) => Shared(Directive::ServerName),
+        ('V', None) => Shared(Directive::CanonicalServerName),
+        _ => return None,
+    })
+}
+
+/// Parse the item following the `%` at `offset`, returning it and the rest of the format.
+fn parse_item(format: &str, offset: usize) -> Result<(ErrorLogItem<'_>, &str), ParseError> {
+    let mut rest = &format[offset + 1..];
+    if rest.is_empty() {
+        return Err(ParseError::TrailingPercent(offset));
+    }
+    if let Some(after) = rest.strip_prefix('%') {
+        return Ok((ErrorLogDirective::Shared(Directive::Literal(Cow::Borrowed("%"))).into(),
+                   after));
+    }
+    let mut item = ErrorLogItem::from(ErrorLogDirective::FieldSeparator);
+    loop {
+        if let Some(after) = rest.strip_prefix('-') {
+            item.hyphen_if_empty = true;
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix('+') {
+            item.required = true;
+            rest = after;
+        } else {
+            break;
+        }
+    }
+    let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+    if digits > 0 {
+        let level = rest[..digits].parse().map_err(|_| ParseError::InvalidDirective(offset))?;
+        item.min_level = Some(level);
+        rest = &rest[digits..];
+    }
+    let mut arg = None;
+    if let Some(after) = rest.strip_prefix('{') {
+        let end = after.find('}').ok_or(ParseError::UnterminatedBrace(offset))?;
+        arg = Some(&after[..end]);
+        rest = &after[end + 1..];
+    }
+    let c = rest.chars().next().ok_or(ParseError::InvalidDirective(offset))?;
+    item.directive = directive(c, arg).ok_or(ParseError::InvalidDirective(offset))?;
+    Ok((item, &rest[c.len_utf8()..]))
+}
+
+/// Parse a complete `ErrorLogFormat` string.
+///
+/// Text between directives, including backslash escapes such as `\ `, is kept verbatim as
+/// literals. Errors use the same offsets as `parse_str`.
+pub fn parse_errorlog_format(format: &str) -> Result<Vec<ErrorLogItem<'_>>, ParseError> {
+    let mut items = Vec::new();
+    let mut rest = format;
+    while !rest.is_empty() {
+        let offset = format.len() - rest.len();
+        if rest.starts_with('%') {
+            let (item, after) = parse_item(format, offset)?;
+            items.push(item);
+            rest = after;
+        } else {
+            let end = rest.find('%').unwrap_or(rest.len());
+            let literal = Directive::Literal(Cow::Borrowed(&rest[..end]));
+            items.push(ErrorLogDirective::Shared(literal).into());
+            rest = &rest[end..];
+        }
+    }
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::ErrorLogDirective::*;
+
+    fn literal(s: &str) -> ErrorLogItem<'_> {
+        Shared(Directive::Literal(Cow::Borrowed(s))).into()
+    }
+
+    #[test]
+    fn test_parse_errorlog_format() {
+        let format = "[%t] [%l] [pid %P] %F: %E: [client %a] %M";
+        let items = parse_errorlog_format(format).unwrap();
+        assert_eq!(items,
+                   [literal("["),
+                    Time.into(),
+                    literal("] ["),
+                    LogLevel.into(),
+                    literal("] [pid "),
+                    Shared(Directive::PID(PIDType::PID)).into(),
+                    literal("] "),
+                    SourceFile.into(),
+                    literal(": "),
+                    AprStatus.into(),
+                    literal(": [client "),
+                    ClientAddress.into(),
+                    literal("] "),
+                    Message.into()]);
+        assert_eq!(items.iter().map(ErrorLogItem::to_string).collect::<String>(), format);
+    }
+
+    #[test]
+    fn test_parse_errorlog_format_modifiers() {
+        let format = concat!(r"[%{u}t] [%-m:%l] [pid %P:tid %T] %7F: %E: [client\ %a] ",
+                             r"%M% ,\ referer\ %+{Referer}i");
+        let items = parse_errorlog_format(format).unwrap();
+        assert_eq!(items[1], TimeMicros.into());
+        assert_eq!(items[3],
+                   ErrorLogItem { hyphen_if_empty: true, ..ErrorLogItem::from(Module) });
+        assert_eq!(items[11],
+                   ErrorLogItem { min_level: Some(7), ..ErrorLogItem::from(SourceFile) });
+        assert_eq!(items[14], literal(r": [client\ "));
+        assert_eq!(items[18], FieldSeparator.into());
+        assert_eq!(items[19], literal(r",\ referer\ "));
+        assert_eq!(items[20],
+                   ErrorLogItem {
+                       required: true,
+                       ..ErrorLogItem::from(Shared(Directive::ReqHeader(Cow::from("Referer"))))
+                   });
+        assert_eq!(items.iter().map(ErrorLogItem::to_string).collect::<String>(), format);
+    }
+
+    #[test]
+    fn test_parse_errorlog_format_errors() {
+        assert_eq!(parse_errorlog_format("%M %"), Err(ParseError::TrailingPercent(3)));
+        assert_eq!(parse_errorlog_format("%M %{u"), Err(ParseError::UnterminatedBrace(3)));
+        assert_eq!(parse_errorlog_format("%M %r"), Err(ParseError::InvalidDirective(3)));
+        assert_eq!(parse_errorlog_format("%{x}t"), Err(ParseError::InvalidDirective(0)));
+        assert_eq!(parse_errorlog_format("%{}i"), Err(ParseError::InvalidDirective(0)));
+        assert_eq!(parse_errorlog_format("%999M"), Err(ParseError::InvalidDirective(0)));
+        assert_eq!(parse_errorlog_format("100%% %{c}L"),
+                   Ok(vec![literal("100"), literal("%"), literal(" "), ConnectionLogId.into()]));
+    }
+}
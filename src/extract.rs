@@ -0,0 +1,918 @@
+use std::borrow::Cow;
+use std::fmt::Write;
+
+#[cfg(feature = "regex")]
+use regex::{self, Regex};
+
+use directive::{Directive, TimeUnit};
+#[cfg(feature = "regex")]
+use directive::HttpVersion;
+
+/// Characters which have a special meaning somewhere in a regular expression.
+const META: &str = "\\.+*?()|[]{}^$#&-~";
+
+/// Escape any regex meta characters in a literal.
+fn escape(literal: &str) -> String {
+    let mut escaped = String::with_capacity(literal.len());
+    for c in literal.chars() {
+        if META.contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Whether the directive at `index` is directly enclosed in `"` literals.
+pub fn is_quoted(format: &[Directive], index: usize) -> bool {
+    let before = index.checked_sub(1).and_then(|i| format.get(i));
+    let after = format.get(index + 1);
+    match (before, after) {
+        (Some(Directive::Literal(b)), Some(Directive::Literal(a))) => {
+            b.ends_with('"') && a.starts_with('"')
+        }
+        _ => false,
+    }
+}
+
+/// Matches an IPv4 or IPv6 address, falling back to a hostname.
+const IP_OR_HOST: &str = r"(?:[0-9a-fA-F:.]+|\S+)";
+
+/// Matches an HTTP method, which may be any token as defined by
+/// [RFC 7230](https://tools.ietf.org/html/rfc7230#section-3.2.6) to allow for extension methods.
+const TOKEN: &str = r"[!#$%&'*+.^_`|~0-9A-Za-z-]+";
+
+/// The pattern matching a time logged by `%{format}t`.
+///
+/// The `sec`, `msec`, `usec`, `msec_frac` and `usec_frac` formats are numbers. Otherwise common
+/// strftime conversions are translated, such as `%Y` to `\d{4}`, other text is matched literally,
+/// and any other conversion matches lazily up to whatever follows it.
+fn strftime_pattern(format: &str) -> String {
+    let format = format.trim_start_matches("begin:").trim_start_matches("end:");
+    if matches!(format, "sec" | "msec" | "usec" | "msec_frac" | "usec_frac") {
+        return String::from(r"\d+");
+    }
+    let mut out = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push_str(&escape(c.encode_utf8(&mut [0; 4])));
+            continue;
+        }
+        out.push_str(match chars.next() {
+            Some('Y') | Some('G') => r"\d{4}",
+            Some('C') | Some('d') | Some('g') | Some('H') | Some('I') | Some('m') | Some('M') |
+            Some('S') | Some('U') | Some('V') | Some('W') | Some('y') => r"\d{2}",
+            Some('e') => r"[ \d]\d",
+            Some('j') => r"\d{3}",
+            Some('u') | Some('w') => r"\d",
+            Some('s') => r"\d+",
+            Some('a') | Some('b') | Some('h') => "[A-Za-z]{3}",
+            Some('A') | Some('B') => "[A-Za-z]+",
+            Some('p') => "[AP]M",
+            Some('z') => r"[+\-]\d{4}",
+            Some('Z') => "[A-Za-z]+",
+            Some('D') => r"\d{2}/\d{2}/\d{2}",
+            Some('F') => r"\d{4}\-\d{2}\-\d{2}",
+            Some('R') => r"\d{2}:\d{2}",
+            Some('T') => r"\d{2}:\d{2}:\d{2}",
+            Some('n') => r"\n",
+            Some('t') => r"\t",
+            Some('%') => "%",
+            _ => ".+?",
+        });
+    }
+    out
+}
+
+/// The pattern used to match the value of a directive.
+///
+/// Directives without a more specific pattern match up to the next whitespace, or up to the
+/// closing quote when they are enclosed in quotes. Apache escapes quotes and backslashes in the
+/// values it logs, so a quote preceded by a backslash, as in a request line of `GET /a\"b`,
+/// doesn't close the value.
+///
+/// `%q` matches without its leading `?`, which `generate` puts outside the capture, so the
+/// captured query string is empty when there is none. `%U` stops at a `?`, so the `%U%q` idiom
+/// splits into the path and query string.
+///
+/// A filename can contain spaces, so an unquoted `%f` matches lazily up to whatever follows it.
+/// That's a best guess when the following field can contain a space too, and `lint::validate`
+/// warns about it.
+fn pattern(directive: &Directive, quoted: bool) -> Cow<'static, str> {
+    use directive::Directive::*;
+    Cow::Borrowed(match *directive {
+        ClientIP | PeerIP | LocalIP | Hostname | ServerName | CanonicalServerName => IP_OR_HOST,
+        Method => TOKEN,
+        Protocol => r"HTTP/[\d.]+",
+        // `%b` logs `-` for an empty response, the other sizes always log a number.
+        ResSize => r"(?:\d+|-)",
+        ResSizeExcludingHeaders | SizeReceived | SizeSent | Size => r"\d+",
+        // Durations in seconds may be fractional, as logged by some forks and modules. A
+        // `TimeUnit::Custom` unit could be anything, so falls through to the general pattern.
+        ReqServeTime | ReqServeTimeUnit(TimeUnit::Seconds) => r"\d+(?:\.\d+)?",
+        ReqTime | ReqServeTimeUnit(TimeUnit::Milliseconds) |
+        ReqServeTimeUnit(TimeUnit::Microseconds) => r"\d+",
+        // Apache writes the brackets around the default time format itself, so they aren't
+        // literals in the format, and the time contains a space.
+        ReqRecvTime => r"\[[^\]]*\]",
+        Query => r"\S*",
+        Path => r"[^\s?]+",
+        ReqRecvTimeFmt(ref format) => return Cow::Owned(strftime_pattern(format)),
+        // A directive with a status condition logs `-` for the other statuses.
+        Conditional(ref condition) => {
+            return Cow::Owned(format!("(?:{}|-)", pattern(&condition.directive, quoted)));
+        }
+        _ if quoted => r#"(?:[^"\\]|\\.)*"#,
+        Filename => r".+?",
+        _ => r"\S+",
+    })
+}
+
+/// The standard grok pattern used to match the value of a directive, or `None` if there is no
+/// suitable one and the regular expression from `pattern` should be used instead.
+///
+/// That includes quoted values: grok's `DATA` would end one at an escaped quote, and `QS`
+/// includes the quotes, which are literals of the format.
+fn grok_pattern(directive: &Directive, quoted: bool) -> Option<&'static str> {
+    use directive::Directive::*;
+    match *directive {
+        ClientIP | PeerIP | LocalIP | Hostname | ServerName | CanonicalServerName => {
+            Some("IPORHOST")
+        }
+        Method | Protocol | Path | Query | ReqRecvTime | ReqRecvTimeFmt(_) | Conditional(_) => None,
+        ReqServeTime | ReqServeTimeUnit(TimeUnit::Seconds) => Some("NUMBER"),
+        ReqTime | ReqServeTimeUnit(TimeUnit::Milliseconds) |
+        ReqServeTimeUnit(TimeUnit::Microseconds) => Some("INT"),
+        _ if quoted => None,
+        Filename => Some("DATA"),
+        _ => Some("NOTSPACE"),
+    }
+}
+
+/// Build an anchored pattern for `format`, escaping literals and calling `capture` with each
+/// directive, whether it is quoted, and its capture name. The name is `None` for literals and
+/// for repeats of a field which has already been captured.
+fn generate<F>(format: &[Directive], mut capture: F) -> String
+    where F: FnMut(&mut String, &Directive, bool, Option<&str>)
+{
+    let mut out = String::from("^");
+    let mut captured = Vec::new();
+    for (index, directive) in format.iter().enumerate() {
+        if let Directive::Literal(ref literal) = *directive {
+            out.push_str(&escape(literal));
+            continue;
+        }
+        let quoted = is_quoted(format, index);
+        if *directive == Directive::Query {
+            out.push_str(r"\??");
+        }
+        match directive.field_name() {
+            Some(name) => {
+                if captured.contains(&name) {
+                    capture(&mut out, directive, quoted, None);
+                } else {
+                    capture(&mut out, directive, quoted, Some(&name));
+                    captured.push(name);
+                }
+            }
+            None => capture(&mut out, directive, quoted, None),
+        }
+    }
+    out.push('$');
+    out
+}
+
+/// Generate a regular expression matching a complete log line written with `format`.
+///
+/// Each directive is captured in a named group, using its `field_name`. If the same field
+/// appears more than once only the first occurrence is captured.
+pub fn to_regex(format: &[Directive]) -> String {
+    generate(format, |out, directive, quoted, name| {
+        let pattern = pattern(directive, quoted);
+        match name {
+            Some(name) => write!(out, "(?P<{}>{})", name, pattern).unwrap(),
+            None => write!(out, "(?:{})", pattern).unwrap(),
+        }
+    })
+}
+
+/// Generate a regular expression for Python's `re` module matching a complete log line written
+/// with `format`, capturing the same groups as `to_regex`.
+///
+/// The patterns generated only use syntax which both engines share, including the `(?P<name>)`
+/// named groups. The exception is the end anchor: in Python `$` also matches before a trailing
+/// newline, so the pattern ends with `\Z` instead.
+pub fn to_python_regex(format: &[Directive]) -> String {
+    let mut pattern = to_regex(format);
+    pattern.pop();
+    pattern.push_str(r"\Z");
+    pattern
+}
+
+/// Generate a [grok](https://www.elastic.co/guide/en/logstash/current/plugins-filters-grok.html)
+/// pattern matching a complete log line written with `format`, for use with Logstash and
+/// similar tools.
+///
+/// Fields are captured in the same way as `to_regex`, using standard grok patterns where one
+/// is suitable.
+pub fn to_grok(format: &[Directive]) -> String {
+    generate(format, |out, directive, quoted, name| {
+        let pattern = pattern(directive, quoted);
+        match (grok_pattern(directive, quoted), name) {
+            (Some(grok), Some(name)) => write!(out, "%{{{}:{}}}", grok, name).unwrap(),
+            (Some(grok), None) => write!(out, "%{{{}}}", grok).unwrap(),
+            (None, Some(name)) => write!(out, "(?<{}>{})", name, pattern).unwrap(),
+            (None, None) => write!(out, "(?:{})", pattern).unwrap(),
+        }
+    })
+}
+
+/// Generate a regular expression matching the start of a log line written with `format`, up to
+/// and including its first directive, for multiline collectors such as Filebeat or Fluentd to
+/// recognise the first line of each entry.
+pub fn line_start_pattern(format: &[Directive]) -> String {
+    let mut out = String::from("^");
+    for (index, directive) in format.iter().enumerate() {
+        match *directive {
+            Directive::Literal(ref literal) => out.push_str(&escape(literal)),
+            _ => {
+                out.push_str(&pattern(directive, is_quoted(format, index)));
+                break;
+            }
+        }
+    }
+    out
+}
+
+/// Split a log line written with `format` into the values of its directives, without a regular
+/// expression, or `None` if the line doesn't match the format.
+///
+/// Each value runs up to the literal text which follows its directive in the format. Values
+/// enclosed in `"` literals may contain that text, as long as the closing quote isn't escaped with
+/// a `\`, and `%t` values run up to their closing `]`, so `%r` and `%t` split correctly.
+pub fn split_fields<'l>(format: &[Directive], line: &'l str) -> Option<Vec<&'l str>> {
+    let mut rest = line;
+    let mut fields = Vec::new();
+    for (index, directive) in format.iter().enumerate() {
+        if let Directive::Literal(ref literal) = *directive {
+            rest = rest.strip_prefix(&**literal)?;
+            continue;
+        }
+        let end = if *directive == Directive::ReqRecvTime && rest.starts_with('[') {
+            rest.find(']')? + 1
+        } else {
+            match format.get(index + 1) {
+                Some(Directive::Literal(next)) if is_quoted(format, index) => {
+                    rest.match_indices(&**next)
+                        .map(|(i, _)| i)
+                        .find(|&i| !rest[..i].ends_with('\\'))?
+                }
+                Some(Directive::Literal(next)) => rest.find(&**next)?,
+                None => rest.len(),
+                // Adjacent directives can't be told apart without knowing what they log.
+                Some(_) => return None,
+            }
+        };
+        fields.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+    if rest.is_empty() { Some(fields) } else { None }
+}
+
+/// Extracts the fields of log lines written with a particular format.
+///
+/// The regular expression for the format is compiled once, when the extractor is built, so
+/// build one extractor and reuse it for every line.
+#[cfg(feature = "regex")]
+#[derive(Debug)]
+pub struct Extractor {
+    regex: Regex,
+    check_logio: bool,
+    multiline: bool,
+    /// The number of physical lines each record is written over: one more than the number of
+    /// newlines in the format's literals.
+    record_lines: usize,
+    /// The field names of the request durations in the format.
+    timings: Vec<String>,
+}
+
+#[cfg(feature = "regex")]
+impl Extractor {
+    /// Build an extractor for lines written with `format`.
+    pub fn new(format: &[Directive]) -> Result<Extractor, regex::Error> {
+        let timings = format.iter()
+            .filter(|directive| directive.timing_base_unit().is_some())
+            .filter_map(|directive| directive.field_name())
+            .map(|name| name.into_owned())
+            .collect();
+        let newlines: usize = format.iter()
+            .map(|directive| match *directive {
+                Directive::Literal(ref literal) => literal.matches('\n').count(),
+                _ => 0,
+            })
+            .sum();
+        Ok(Extractor {
+            regex: Regex::new(&to_regex(format))?,
+            check_logio: false,
+            multiline: false,
+            record_lines: newlines + 1,
+            timings,
+        })
+    }
+
+    /// Warn about `%I` and `%S` values of `0`. These count request headers so can never really
+    /// be zero, and usually mean mod_logio isn't loaded. Off by default.
+    pub fn check_logio(mut self, check: bool) -> Extractor {
+        self.check_logio = check;
+        self
+    }
+
+    /// Treat newlines in the format's literals as splitting each record over several physical
+    /// lines, which `parse_lines` groups back together. Off by default, when every line of the
+    /// input to `parse_lines` is a record of its own.
+    pub fn multiline(mut self, multiline: bool) -> Extractor {
+        self.multiline = multiline;
+        self
+    }
+
+    /// Extract the records from `text`, made up of lines separated by `\n`, yielding `None` for
+    /// each record which doesn't match the format. See `multiline`.
+    pub fn parse_lines<'l>(&self, text: &'l str) -> Records<'_, 'l> {
+        Records { extractor: self, rest: text }
+    }
+
+    /// Extract the fields from a single log line, or `None` if the line doesn't match the
+    /// format. With a format containing newlines, the line is the complete record.
+    pub fn parse_line<'l>(&self, line: &'l str) -> Option<Record<'_, 'l>> {
+        let captures = self.regex.captures(line)?;
+        let fields: Vec<_> = self.regex
+            .capture_names()
+            .flatten()
+            .filter_map(|name| captures.name(name).map(|m| (name, m.as_str())))
+            .collect();
+        let mut warnings = Vec::new();
+        for &(name, value) in &fields {
+            if self.timings.iter().any(|t| t == name) && value.parse::<f64>().is_err() {
+                warnings.push(ExtractWarning::NonNumericTiming(name));
+            }
+        }
+        if self.check_logio {
+            for &(name, value) in &fields {
+                if value == "0" && LOGIO_NON_ZERO.contains(&name) {
+                    warnings.push(ExtractWarning::ZeroByteCount(name));
+                }
+            }
+        }
+        Some(Record { fields, warnings })
+    }
+}
+
+/// An iterator over the records in a block of text. See `Extractor::parse_lines`.
+#[cfg(feature = "regex")]
+#[derive(Debug, Clone)]
+pub struct Records<'e, 'l> {
+    extractor: &'e Extractor,
+    rest: &'l str,
+}
+
+#[cfg(feature = "regex")]
+impl<'e, 'l> Iterator for Records<'e, 'l> {
+    type Item = Option<Record<'e, 'l>>;
+
+    fn next(&mut self) -> Option<Option<Record<'e, 'l>>> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let lines = if self.extractor.multiline { self.extractor.record_lines } else { 1 };
+        let record = match self.rest.match_indices('\n').nth(lines - 1) {
+            Some((end, _)) => {
+                let record = &self.rest[..end];
+                self.rest = &self.rest[end + 1..];
+                record
+            }
+            None => std::mem::take(&mut self.rest),
+        };
+        Some(self.extractor.parse_line(record))
+    }
+}
+
+/// The fields of `%I` and `%S`, which include the request line and headers.
+#[cfg(feature = "regex")]
+const LOGIO_NON_ZERO: [&str; 2] = ["bytes_received", "bytes_transferred"];
+
+/// A suspicious value found while extracting a log line, which doesn't prevent extraction.
+#[cfg(feature = "regex")]
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExtractWarning<'e> {
+    /// A mod_logio byte count, which can't be zero, was logged as `0`. Gives the field name.
+    ZeroByteCount(&'e str),
+    /// A request duration which isn't a number. Only possible for a `TimeUnit::Custom` unit,
+    /// as the others only match numbers. Gives the field name.
+    NonNumericTiming(&'e str),
+}
+
+/// The fields extracted from a single log line, keyed by field name.
+#[cfg(feature = "regex")]
+#[derive(Debug, PartialEq)]
+pub struct Record<'e, 'l> {
+    fields: Vec<(&'e str, &'l str)>,
+    warnings: Vec<ExtractWarning<'e>>,
+}
+
+#[cfg(feature = "regex")]
+impl<'e, 'l> Record<'e, 'l> {
+    /// The value of the field `name`, if it was captured.
+    pub fn get(&self, name: &str) -> Option<&'l str> {
+        self.fields.iter().find(|&&(n, _)| n == name).map(|&(_, v)| v)
+    }
+
+    /// The value of the field `name`, or `None` if it wasn't captured or was logged as `-`, as
+    /// Apache does for missing values such as `%R` with no handler or `%L` with no error.
+    pub fn value(&self, name: &str) -> Option<&'l str> {
+        self.get(name).filter(|&v| v != "-")
+    }
+
+    /// The `%b` response size, if it was captured, with the `-` logged for an empty response
+    /// read as zero.
+    pub fn response_size(&self) -> Option<u64> {
+        match self.get("response_size")? {
+            "-" => Some(0),
+            size => size.parse().ok(),
+        }
+    }
+
+    /// The `%H` protocol, if it was captured and is a known HTTP version.
+    pub fn protocol(&self) -> Option<HttpVersion> {
+        self.get("protocol").and_then(Directive::parse_protocol)
+    }
+
+    /// Iterate over the captured `(field name, value)` pairs, in format order.
+    pub fn iter(&self) -> ::std::slice::Iter<'_, (&'e str, &'l str)> {
+        self.fields.iter()
+    }
+
+    /// Suspicious values found in the line, such as a non-numeric request duration. See also
+    /// `Extractor::check_logio`.
+    pub fn warnings(&self) -> &[ExtractWarning<'e>] {
+        &self.warnings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use directive::Directive;
+    #[cfg(feature = "regex")]
+    use directive::DirectiveKind;
+    use format::LogFormat;
+    use parser::parse_str;
+    use CLF;
+    #[cfg(feature = "regex")]
+    use VHOST_COMBINED;
+
+    #[test]
+    fn test_to_regex() {
+        let format = [Directive::Hostname,
+                      Directive::Literal(Cow::from(" [")),
+                      Directive::Protocol,
+                      Directive::Literal(Cow::from("]"))];
+        assert_eq!(to_regex(&format),
+                   r"^(?P<hostname>(?:[0-9a-fA-F:.]+|\S+)) \[(?P<protocol>HTTP/[\d.]+)\]$");
+    }
+
+    #[test]
+    fn test_to_regex_quoted() {
+        let format = [Directive::Literal(Cow::from("\"")),
+                      Directive::ReqFirstLine,
+                      Directive::Literal(Cow::from("\""))];
+        assert_eq!(to_regex(&format), r#"^"(?P<request_line>(?:[^"\\]|\\.)*)"$"#);
+    }
+
+    #[test]
+    fn test_to_regex_repeated_field() {
+        let format = [Directive::User, Directive::Literal(Cow::from(" ")), Directive::User];
+        assert_eq!(to_regex(&format), r"^(?P<user>\S+) (?:\S+)$");
+    }
+
+    #[test]
+    fn test_to_regex_client_and_peer_ip() {
+        let format = [Directive::ClientIP, Directive::Literal(Cow::from(" ")), Directive::PeerIP];
+        assert_eq!(to_regex(&format),
+                   concat!(r"^(?P<client_ip>(?:[0-9a-fA-F:.]+|\S+)) ",
+                           r"(?P<peer_ip>(?:[0-9a-fA-F:.]+|\S+))$"));
+    }
+
+    #[test]
+    fn test_line_start_pattern() {
+        let format = [Directive::Hostname, Directive::Literal(Cow::from(" ")), Directive::User];
+        assert_eq!(line_start_pattern(&format), r"^(?:[0-9a-fA-F:.]+|\S+)");
+        let format = [Directive::Literal(Cow::from("[")),
+                      Directive::ReqRecvTimeFmt(Cow::from("%d/%b/%Y"))];
+        assert_eq!(line_start_pattern(&format), r"^\[\d{2}/[A-Za-z]{3}/\d{4}");
+    }
+
+    #[test]
+    fn test_to_regex_time() {
+        let format = [Directive::User, Directive::Literal(Cow::from(" ")), Directive::ReqRecvTime];
+        assert_eq!(to_regex(&format), r"^(?P<user>\S+) (?P<time>\[[^\]]*\])$");
+        let format = parse_str("%t %{msec}t").unwrap();
+        assert_eq!(to_regex(&format), r"^(?P<time>\[[^\]]*\]) (?P<time_msec>\d+)$");
+    }
+
+    #[test]
+    fn test_to_regex_conditional() {
+        let format = parse_str("%h %!200,304{Referer}i").unwrap();
+        assert_eq!(to_regex(&format),
+                   r"^(?P<hostname>(?:[0-9a-fA-F:.]+|\S+)) (?P<req_header_referer>(?:\S+|-))$");
+        assert_eq!(to_grok(&format),
+                   r"^%{IPORHOST:hostname} (?<req_header_referer>(?:\S+|-))$");
+    }
+
+    #[test]
+    fn test_split_fields() {
+        let format = parse_str(CLF).unwrap();
+        let line = concat!(r#"127.0.0.1 - frank [10/Oct/2000:13:55:36 -0700] "#,
+                           r#""GET /a \"b\" c HTTP/1.0" 200 2326"#);
+        assert_eq!(split_fields(&format, line),
+                   Some(vec!["127.0.0.1",
+                             "-",
+                             "frank",
+                             "[10/Oct/2000:13:55:36 -0700]",
+                             r#"GET /a \"b\" c HTTP/1.0"#,
+                             "200",
+                             "2326"]));
+        assert_eq!(split_fields(&format, "127.0.0.1 - frank"), None);
+    }
+
+    #[test]
+    fn test_split_fields_tab_separated() {
+        // The escape `\t` is decoded to a tab, as is usual for tab-separated formats.
+        let format = LogFormat::parse("%h\\t%l\\t%u").unwrap();
+        assert_eq!(&format[..],
+                   &[Directive::Hostname,
+                     Directive::Literal(Cow::from("\t")),
+                     Directive::Logname,
+                     Directive::Literal(Cow::from("\t")),
+                     Directive::User]);
+        assert_eq!(format.split_quoted_fields("127.0.0.1\t-\tfrank"),
+                   Some(vec!["127.0.0.1", "-", "frank"]));
+        assert_eq!(format.split_quoted_fields("127.0.0.1 - frank"), None);
+        // Tabs written into the format itself work the same way.
+        let format = parse_str("%h\t%u\t%r\t%>s").unwrap();
+        assert_eq!(split_fields(&format, "127.0.0.1\tfrank\tGET / HTTP/1.0\t200"),
+                   Some(vec!["127.0.0.1", "frank", "GET / HTTP/1.0", "200"]));
+    }
+
+    #[test]
+    fn test_to_python_regex() {
+        let format = parse_str(CLF).unwrap();
+        assert_eq!(to_python_regex(&format),
+                   concat!(r"^(?P<hostname>(?:[0-9a-fA-F:.]+|\S+)) (?P<logname>\S+) ",
+                           r"(?P<user>\S+) (?P<time>\[[^\]]*\]) ",
+                           r#""(?P<request_line>(?:[^"\\]|\\.)*)" (?P<status>\S+) "#,
+                           r"(?P<response_size>(?:\d+|-))\Z"));
+        assert_eq!(to_python_regex(&[]), r"^\Z");
+    }
+
+    #[test]
+    fn test_to_grok() {
+        let format = [Directive::Hostname,
+                      Directive::Literal(Cow::from(" \"")),
+                      Directive::ReqFirstLine,
+                      Directive::Literal(Cow::from("\" ")),
+                      Directive::Method,
+                      Directive::Literal(Cow::from(" ")),
+                      Directive::User,
+                      Directive::Literal(Cow::from(" ")),
+                      Directive::User];
+        assert_eq!(to_grok(&format),
+                   concat!(r#"^%{IPORHOST:hostname} "(?<request_line>(?:[^"\\]|\\.)*)" "#,
+                           r"(?<method>[!#$%&'*+.^_`|~0-9A-Za-z-]+) ",
+                           r"%{NOTSPACE:user} %{NOTSPACE}$"));
+    }
+
+    #[test]
+    fn test_trailer_captures() {
+        let format = [Directive::Literal(Cow::from("\"")),
+                      Directive::ResTrailer(Cow::from("Expires")),
+                      Directive::Literal(Cow::from("\" ")),
+                      Directive::ReqTrailer(Cow::from("Expires")),
+                      Directive::Literal(Cow::from(" ")),
+                      Directive::ResHeader(Cow::from("Expires"))];
+        assert_eq!(to_regex(&format),
+                   concat!(r#"^"(?P<res_trailer_expires>(?:[^"\\]|\\.)*)" "#,
+                           r"(?P<req_trailer_expires>\S+) (?P<res_header_expires>\S+)$"));
+        assert_eq!(to_grok(&format),
+                   concat!(r#"^"(?<res_trailer_expires>(?:[^"\\]|\\.)*)" "#,
+                           r"%{NOTSPACE:req_trailer_expires} %{NOTSPACE:res_header_expires}$"));
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_to_grok_escaped_quote() {
+        let format = parse_str("\"%{User-agent}i\" %>s").unwrap();
+        let grok = to_grok(&format);
+        assert_eq!(grok, r#"^"(?<req_header_user_agent>(?:[^"\\]|\\.)*)" %{NOTSPACE:status}$"#);
+        // Grok's own patterns aside, the pattern is a regular expression with named groups.
+        let pattern = Regex::new(&grok.replace("%{NOTSPACE:status}", r"(?<status>\S+)")).unwrap();
+        let captures = pattern.captures(r#""Mozilla \"compatible\" x" 200"#).unwrap();
+        assert_eq!(&captures["req_header_user_agent"], r#"Mozilla \"compatible\" x"#);
+        assert_eq!(&captures["status"], "200");
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_extract_header_direction() {
+        let format = parse_str("\"%{Content-Type}i\" \"%{Content-Type}o\"").unwrap();
+        assert_eq!(to_regex(&format),
+                   concat!(r#"^"(?P<req_header_content_type>(?:[^"\\]|\\.)*)" "#,
+                           r#""(?P<res_header_content_type>(?:[^"\\]|\\.)*)"$"#));
+        let extractor = Extractor::new(&format).unwrap();
+        let record = extractor.parse_line(r#""application/json" "text/html; charset=UTF-8""#)
+            .unwrap();
+        assert_eq!(record.get("req_header_content_type"), Some("application/json"));
+        assert_eq!(record.get("res_header_content_type"), Some("text/html; charset=UTF-8"));
+    }
+
+    #[test]
+    fn test_strftime_pattern() {
+        assert_eq!(strftime_pattern("%d/%b/%Y:%T %z"),
+                   r"\d{2}/[A-Za-z]{3}/\d{4}:\d{2}:\d{2}:\d{2} [+\-]\d{4}");
+        assert_eq!(strftime_pattern("%F %c 100%%"), r"\d{4}\-\d{2}\-\d{2} .+? 100%");
+        assert_eq!(strftime_pattern("end:msec"), r"\d+");
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_extract_custom_time() {
+        let format = parse_str("%h [%{%Y-%m-%dT%H:%M:%S}t] %>s").unwrap();
+        assert_eq!(to_grok(&format),
+                   concat!(r"^%{IPORHOST:hostname} \[",
+                           r"(?<time__y__m__dt_h__m__s>\d{4}\-\d{2}\-\d{2}T\d{2}:\d{2}:\d{2})\] ",
+                           r"%{NOTSPACE:status}$"));
+        let extractor = Extractor::new(&format).unwrap();
+        let record = extractor.parse_line("127.0.0.1 [2000-10-10T13:55:36] 200").unwrap();
+        assert_eq!(record.get("time__y__m__dt_h__m__s"), Some("2000-10-10T13:55:36"));
+        assert!(extractor.parse_line("127.0.0.1 [10/Oct/2000:13:55:36] 200").is_none());
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_extract_vhost() {
+        let format = parse_str(VHOST_COMBINED).unwrap();
+        assert!(to_grok(&format)
+            .starts_with("^%{IPORHOST:server_name}:%{NOTSPACE:canonical_port} "));
+        let extractor = Extractor::new(&format).unwrap();
+        let line = concat!(r#"www.example.com:443 127.0.0.1 - - [10/Oct/2000:13:55:36 -0700] "#,
+                           r#""GET / HTTP/1.1" 200 2326 "-" "curl/7.68.0""#);
+        let record = extractor.parse_line(line).unwrap();
+        assert_eq!(record.get("server_name"), Some("www.example.com"));
+        assert_eq!(record.get("canonical_port"), Some("443"));
+        assert_eq!(record.get("hostname"), Some("127.0.0.1"));
+
+        let format = parse_str("%V %v").unwrap();
+        let extractor = Extractor::new(&format).unwrap();
+        let record = extractor.parse_line("10.0.0.1 example.com").unwrap();
+        assert_eq!(record.get("canonical_server_name"), Some("10.0.0.1"));
+        assert_eq!(record.get("server_name"), Some("example.com"));
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_extract_trailer() {
+        let format = [Directive::Literal(Cow::from("\"")),
+                      Directive::ResTrailer(Cow::from("Expires")),
+                      Directive::Literal(Cow::from("\""))];
+        let extractor = Extractor::new(&format).unwrap();
+        let record = extractor.parse_line(r#""Wed, 21 Oct 2015 07:28:00 GMT""#).unwrap();
+        assert_eq!(record.get("res_trailer_expires"), Some("Wed, 21 Oct 2015 07:28:00 GMT"));
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_extract_zero_logio() {
+        let format = [Directive::SizeReceived,
+                      Directive::Literal(Cow::from(" ")),
+                      Directive::SizeSent];
+        let extractor = Extractor::new(&format).unwrap().check_logio(true);
+        let record = extractor.parse_line("0 0").unwrap();
+        assert_eq!(record.get("bytes_received"), Some("0"));
+        assert_eq!(record.warnings(), [ExtractWarning::ZeroByteCount("bytes_received")]);
+        assert!(extractor.parse_line("468 0").unwrap().warnings().is_empty());
+        let unchecked = Extractor::new(&format).unwrap();
+        assert!(unchecked.parse_line("0 0").unwrap().warnings().is_empty());
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_extract_timing() {
+        let format = parse_str("%D %T %{ms}T %{min}T").unwrap();
+        assert_eq!(to_regex(&format),
+                   concat!(r"^(?P<request_duration_us>\d+) (?P<request_duration_s>\d+(?:\.\d+)?) ",
+                           r"(?P<request_duration_ms>\d+) (?P<request_duration_min>\S+)$"));
+        assert_eq!(to_grok(&format),
+                   concat!(r"^%{INT:request_duration_us} %{NUMBER:request_duration_s} ",
+                           r"%{INT:request_duration_ms} %{NOTSPACE:request_duration_min}$"));
+        let extractor = Extractor::new(&format).unwrap();
+        let record = extractor.parse_line("250123 0.25 250 4").unwrap();
+        assert_eq!(record.get("request_duration_us"), Some("250123"));
+        assert_eq!(record.get("request_duration_s"), Some("0.25"));
+        assert!(record.warnings().is_empty());
+        let record = extractor.parse_line("250123 0 250 abc").unwrap();
+        assert_eq!(record.warnings(), [ExtractWarning::NonNumericTiming("request_duration_min")]);
+        assert!(extractor.parse_line("250.5 0 250 4").is_none());
+        assert!(extractor.parse_line("250 - 250 4").is_none());
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_extract_multiline() {
+        let format = parse_str("%h %>s\n  \"%r\"").unwrap();
+        let text = "127.0.0.1 200\n  \"GET / HTTP/1.1\"\n10.0.0.1 404\n  \"GET /a HTTP/1.1\"\n";
+        assert_eq!(split_fields(&format, "127.0.0.1 200\n  \"GET / HTTP/1.1\""),
+                   Some(vec!["127.0.0.1", "200", "GET / HTTP/1.1"]));
+
+        let extractor = Extractor::new(&format).unwrap().multiline(true);
+        let records: Vec<_> = extractor.parse_lines(text).map(Option::unwrap).collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].get("request_line"), Some("GET / HTTP/1.1"));
+        assert_eq!(records[1].get("hostname"), Some("10.0.0.1"));
+        assert_eq!(records[1].get("status"), Some("404"));
+
+        let extractor = Extractor::new(&format).unwrap();
+        assert_eq!(extractor.parse_lines(text).filter(Option::is_none).count(), 4);
+        let single = Extractor::new(&parse_str("%h").unwrap()).unwrap().multiline(true);
+        assert_eq!(single.parse_lines("a\nb").flatten().count(), 2);
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_extract_clf_time() {
+        let extractor = Extractor::new(&parse_str(CLF).unwrap()).unwrap();
+        let line = concat!(r#"192.0.2.1 - - [18/Sep/2011:19:18:28 -0400] "#,
+                           r#""GET /index.html HTTP/1.1" 304 -"#);
+        let record = extractor.parse_line(line).unwrap();
+        assert_eq!(record.get("time"), Some("[18/Sep/2011:19:18:28 -0400]"));
+        assert_eq!(record.get("request_line"), Some("GET /index.html HTTP/1.1"));
+        assert_eq!(record.get("response_size"), Some("-"));
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_extract_handler_and_error_id() {
+        let extractor = Extractor::new(&parse_str("%R %L %>s").unwrap()).unwrap();
+        let record = extractor.parse_line("- - 200").unwrap();
+        assert_eq!(record.get("response_handler"), Some("-"));
+        assert_eq!(record.value("response_handler"), None);
+        assert_eq!(record.value("error_log_id"), None);
+        assert_eq!(record.value("status"), Some("200"));
+        let record = extractor.parse_line("php-script WZqO8n8AAQEAAAfQxUcAAAAA 500").unwrap();
+        assert_eq!(record.value("response_handler"), Some("php-script"));
+        assert_eq!(record.value("error_log_id"), Some("WZqO8n8AAQEAAAfQxUcAAAAA"));
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_extract_method() {
+        let format = [Directive::Method, Directive::Literal(Cow::from(" ")), Directive::Path];
+        let extractor = Extractor::new(&format).unwrap();
+        assert_eq!(extractor.parse_line("GET /").unwrap().get("method"), Some("GET"));
+        assert_eq!(extractor.parse_line("PATCH /a").unwrap().get("method"), Some("PATCH"));
+        assert_eq!(extractor.parse_line("X-PURGE /a").unwrap().get("method"), Some("X-PURGE"));
+        assert!(extractor.parse_line("GE\"T /").is_none());
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_extract_query() {
+        let format = parse_str("%m %U%q %>s").unwrap();
+        assert_eq!(to_regex(&format),
+                   concat!(r"^(?P<method>[!#$%&'*+.^_`|~0-9A-Za-z-]+) (?P<url_path>[^\s?]+)",
+                           r"\??(?P<query>\S*) (?P<status>\S+)$"));
+        let extractor = Extractor::new(&format).unwrap();
+        let record = extractor.parse_line("GET /search?foo=bar&x=1 200").unwrap();
+        assert_eq!(record.get("url_path"), Some("/search"));
+        assert_eq!(record.get("query"), Some("foo=bar&x=1"));
+        let record = extractor.parse_line("GET /search 200").unwrap();
+        assert_eq!(record.get("query"), Some(""));
+
+        let extractor = Extractor::new(&parse_str("%q").unwrap()).unwrap();
+        assert_eq!(extractor.parse_line("?foo=bar").unwrap().get("query"), Some("foo=bar"));
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_extract_ssl_var() {
+        let format = parse_str("%h %{SSL_PROTOCOL}x %{SSL_CIPHER}x").unwrap();
+        assert_eq!(format[2].field_name(), Some(Cow::from("ssl_protocol")));
+        assert_eq!(format[2].kind(), DirectiveKind::Connection);
+        assert_eq!(to_grok(&format),
+                   r"^%{IPORHOST:hostname} %{NOTSPACE:ssl_protocol} %{NOTSPACE:ssl_cipher}$");
+        let extractor = Extractor::new(&format).unwrap();
+        let record = extractor.parse_line("127.0.0.1 TLSv1.3 TLS_AES_256_GCM_SHA384").unwrap();
+        assert_eq!(record.get("ssl_protocol"), Some("TLSv1.3"));
+        assert_eq!(record.get("ssl_cipher"), Some("TLS_AES_256_GCM_SHA384"));
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_extract_protocol() {
+        let format = [Directive::Method,
+                      Directive::Literal(Cow::from(" ")),
+                      Directive::Protocol,
+                      Directive::Literal(Cow::from(" ")),
+                      Directive::FinalStatus];
+        let extractor = Extractor::new(&format).unwrap();
+        let record = extractor.parse_line("GET HTTP/1.1 200").unwrap();
+        assert_eq!(record.get("protocol"), Some("HTTP/1.1"));
+        assert_eq!(record.protocol(), Some(HttpVersion::Http11));
+        assert_eq!(record.get("status"), Some("200"));
+        assert_eq!(extractor.parse_line("GET HTTP/2.0 200").unwrap().protocol(),
+                   Some(HttpVersion::Http2));
+        assert_eq!(extractor.parse_line("GET HTTP/0.9 200").unwrap().protocol(), None);
+        assert!(extractor.parse_line("GET SPDY/3 200").is_none());
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_extract_reuse() {
+        let extractor = Extractor::new(&parse_str(CLF).unwrap()).unwrap();
+        for i in 0..100 {
+            let line = format!(r#"10.0.0.{} - - [10/Oct/2000:13:55:36 -0700] "GET /" 200 {}"#,
+                               i,
+                               i * 10);
+            let record = extractor.parse_line(&line).unwrap();
+            assert_eq!(record.get("hostname"), Some(&*format!("10.0.0.{}", i)));
+            assert_eq!(record.response_size(), Some(i * 10));
+        }
+        assert!(extractor.parse_line("not a log line").is_none());
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_extract_escaped_quote() {
+        let extractor = Extractor::new(&parse_str(CLF).unwrap()).unwrap();
+        let line = concat!(r#"127.0.0.1 - - [10/Oct/2000:13:55:36 -0700] "#,
+                           r#""GET /a\"b\\ HTTP/1.0" 200 2326"#);
+        let record = extractor.parse_line(line).unwrap();
+        assert_eq!(record.get("request_line"), Some(r#"GET /a\"b\\ HTTP/1.0"#));
+        assert_eq!(record.get("status"), Some("200"));
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_extract_filename_with_space() {
+        let extractor = Extractor::new(&parse_str("\"%f\" %>s").unwrap()).unwrap();
+        let record = extractor.parse_line(r#""/var/www/My Documents/a b.html" 200"#).unwrap();
+        assert_eq!(record.get("filename"), Some("/var/www/My Documents/a b.html"));
+        assert_eq!(record.get("status"), Some("200"));
+
+        let extractor = Extractor::new(&parse_str("%f %>s").unwrap()).unwrap();
+        let record = extractor.parse_line("/var/www/a b.html 200").unwrap();
+        assert_eq!(record.get("filename"), Some("/var/www/a b.html"));
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_extract_sizes() {
+        let extractor = Extractor::new(&parse_str("%O %b").unwrap()).unwrap();
+        let record = extractor.parse_line("312 -").unwrap();
+        assert_eq!(record.get("bytes_sent"), Some("312"));
+        assert_eq!(record.get("response_size"), Some("-"));
+        assert_eq!(record.value("response_size"), None);
+        assert_eq!(record.response_size(), Some(0));
+        assert_eq!(extractor.parse_line("2638 2326").unwrap().response_size(), Some(2326));
+        // mod_logio always logs a count, so `-` can't be a `%O`.
+        assert!(extractor.parse_line("- 2326").is_none());
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_extract_client_and_peer_ip() {
+        let format = [Directive::ClientIP, Directive::Literal(Cow::from(" ")), Directive::PeerIP];
+        let extractor = Extractor::new(&format).unwrap();
+        let record = extractor.parse_line("203.0.113.7 10.0.0.1").unwrap();
+        assert_eq!(record.get("client_ip"), Some("203.0.113.7"));
+        assert_eq!(record.get("peer_ip"), Some("10.0.0.1"));
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_extract_ip() {
+        let format = [Directive::Hostname,
+                      Directive::Literal(Cow::from(" ")),
+                      Directive::ClientIP,
+                      Directive::Literal(Cow::from(" ")),
+                      Directive::User];
+        let extractor = Extractor::new(&format).unwrap();
+        let record = extractor.parse_line("2001:db8::ff00:42:8329 10.0.0.1 frank").unwrap();
+        assert_eq!(record.get("hostname"), Some("2001:db8::ff00:42:8329"));
+        assert_eq!(record.get("client_ip"), Some("10.0.0.1"));
+        assert_eq!(record.get("user"), Some("frank"));
+        let record = extractor.parse_line("www.example.com ::1 -").unwrap();
+        assert_eq!(record.get("hostname"), Some("www.example.com"));
+        assert_eq!(record.get("client_ip"), Some("::1"));
+    }
+}
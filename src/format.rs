@@ -0,0 +1,960 @@
+use std::borrow::Cow;
+use std::error;
+use std::fmt;
+use std::ops::{Deref, Range};
+use std::str::FromStr;
+
+#[cfg(feature = "color")]
+use owo_colors::OwoColorize;
+#[cfg(feature = "regex")]
+use regex;
+#[cfg(feature = "smallvec")]
+use smallvec::SmallVec;
+
+use binary::{self, DecodeError};
+use config;
+use directive::{Directive, TimeUnit};
+#[cfg(feature = "color")]
+use directive::DirectiveKind;
+use extract;
+#[cfg(feature = "regex")]
+use extract::Extractor;
+use lint::{self, Ambiguity, FormatWarning};
+use named::{self, NamedFormat};
+use parser::{self, DirectiveOffsets, ParseError, ParseOptions, ParserBuilder};
+use render::{self, LogSource, RenderOptions};
+use schema;
+
+/// A field name passed to `LogFormat::from_field_names` which doesn't name any directive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownField(pub String);
+
+impl fmt::Display for UnknownField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown field name '{}'", self.0)
+    }
+}
+
+impl error::Error for UnknownField {}
+
+/// The directive logging the field called `name`. See `LogFormat::from_field_names`.
+fn field_directive(name: &str) -> Option<Directive<'static>> {
+    if let Some((kind, arg)) = name.split_once(':') {
+        let arg = Cow::Owned(arg.to_owned());
+        return match kind {
+            "header" => Some(Directive::ReqHeader(arg)),
+            "response_header" => Some(Directive::ResHeader(arg)),
+            "cookie" => Some(Directive::Cookie(arg)),
+            "env" => Some(Directive::EnvVar(arg)),
+            "note" => Some(Directive::Note(arg)),
+            "ssl" => Some(Directive::SslVar(arg)),
+            _ => None,
+        };
+    }
+    Directive::all_niladic()
+        .iter()
+        .find(|directive| directive.field_name().is_some_and(|field| field == name))
+        .cloned()
+}
+
+/// The directives of a `LogFormat`. With the `smallvec` feature, formats of up to 16
+/// directives, such as the Common Log Format, are stored inline rather than on the heap.
+#[cfg(feature = "smallvec")]
+type Storage<'a> = SmallVec<[Directive<'a>; 16]>;
+#[cfg(not(feature = "smallvec"))]
+type Storage<'a> = Vec<Directive<'a>>;
+
+/// Where each directive of a parsed format came from in the format string, stored alongside
+/// `Storage`.
+#[cfg(feature = "smallvec")]
+type Spans = SmallVec<[Span; 16]>;
+#[cfg(not(feature = "smallvec"))]
+type Spans = Vec<Span>;
+
+/// Where a directive of a parsed format came from in the format string.
+#[derive(Debug, Clone)]
+struct Span {
+    /// The byte range of the format string the directive was parsed from.
+    range: Range<usize>,
+    /// Whether the directive is unchanged since it was parsed, so it's written back as it was
+    /// spelled rather than as the directive displays.
+    verbatim: bool,
+}
+
+/// Mark the directive at `index` as changed since it was parsed.
+fn rewritten(spans: &mut [Span], index: usize) {
+    if let Some(span) = spans.get_mut(index) {
+        span.verbatim = false;
+    }
+}
+
+/// The directive `directive` logs when its status condition holds, as
+/// `Directive::unconditional`, for changing it in place.
+fn unconditional_mut<'d, 'a>(directive: &'d mut Directive<'a>) -> &'d mut Directive<'a> {
+    match *directive {
+        Directive::Conditional(ref mut condition) => &mut condition.directive,
+        ref mut directive => directive,
+    }
+}
+
+/// A complete, parsed log format.
+///
+/// Dereferences to the slice of directives making up the format. A format parsed from a string
+/// remembers how each directive was spelled, so that it displays as it was written: `%p` and
+/// `%{canonical}p` are the same directive, but each is written back as given.
+#[derive(Debug, Clone)]
+pub struct LogFormat<'a> {
+    directives: Storage<'a>,
+    /// The format string the directives were parsed from, or empty if they weren't.
+    source: Cow<'a, str>,
+    /// Where each directive came from in `source`, or empty if they weren't parsed.
+    spans: Spans,
+}
+
+impl<'a> LogFormat<'a> {
+    /// A format made up of `directives`, which weren't parsed from a string.
+    fn new(directives: Storage<'a>) -> LogFormat<'a> {
+        LogFormat { directives, source: Cow::Borrowed(""), spans: Spans::new() }
+    }
+
+    /// Collect the directives parsed from `format`, along with where each came from.
+    fn collect_offsets(format: &'a str,
+                       offsets: DirectiveOffsets<'a>)
+                       -> Result<LogFormat<'a>, ParseError> {
+        let mut directives = Storage::new();
+        let mut spans = Spans::new();
+        for parsed in offsets {
+            let (directive, range) = parsed?;
+            directives.push(directive);
+            spans.push(Span { range, verbatim: true });
+        }
+        Ok(LogFormat { directives, source: Cow::Borrowed(format), spans })
+    }
+
+    /// The text the directive at `index` was parsed from, if it hasn't changed since.
+    fn verbatim(&self, index: usize) -> Option<&str> {
+        self.spans
+            .get(index)
+            .filter(|span| span.verbatim)
+            .map(|span| &self.source[span.range.clone()])
+    }
+
+    /// How the directive at `index` is written in the format string.
+    fn spelling(&self, index: usize) -> Cow<'_, str> {
+        match self.verbatim(index) {
+            Some(text) => Cow::Borrowed(text),
+            None => Cow::Owned(self.directives[index].to_string()),
+        }
+    }
+
+    /// Build a format logging the fields called `names`, as given by `Directive::field_name`,
+    /// separated by single spaces.
+    ///
+    /// Fields with an argument are written `header:NAME` for a request header, and likewise
+    /// `response_header:`, `cookie:`, `env:`, `note:` and `ssl:`. Where several directives log
+    /// the same field, such as `%T` and `%{s}T`, the shorter form is used.
+    pub fn from_field_names(names: &[&str]) -> Result<LogFormat<'static>, UnknownField> {
+        let mut directives = Storage::with_capacity(names.len() * 2);
+        for (index, &name) in names.iter().enumerate() {
+            if index > 0 {
+                directives.push(Directive::Literal(Cow::Borrowed(" ")));
+            }
+            let directive = field_directive(name).ok_or_else(|| UnknownField(name.to_owned()))?;
+            directives.push(directive);
+        }
+        Ok(LogFormat::new(directives))
+    }
+
+    /// Parse a log format string, borrowing from it where possible.
+    pub fn parse(format: &'a str) -> Result<LogFormat<'a>, ParseError> {
+        LogFormat::collect_offsets(format, parser::iter_directives(format).with_offsets())
+    }
+
+    /// Parse a log format string with the given options. See `parse_with`.
+    pub fn parse_with(format: &'a str,
+                      options: &ParseOptions)
+                      -> Result<LogFormat<'a>, ParseError> {
+        let parser = ParserBuilder::new().options(*options).build();
+        LogFormat::collect_offsets(format, parser.iter(format).with_offsets())
+    }
+
+    /// Lazily parse a log format string, yielding each directive with the byte range of `format`
+    /// it was parsed from, without collecting them into a format.
+    pub fn iter_with_offsets(format: &'a str) -> DirectiveOffsets<'a> {
+        parser::iter_directives(format).with_offsets()
+    }
+
+    /// The directive parsed from byte `offset` of the format string, as given by
+    /// `iter_with_offsets`. An offset within a literal gives the literal.
+    ///
+    /// Returns `None` past the end of the format, within whitespace trimmed while parsing, and
+    /// for formats which weren't parsed from a string.
+    pub fn directive_at(&self, offset: usize) -> Option<&Directive<'a>> {
+        let index = self.spans.partition_point(|span| span.range.end <= offset);
+        match self.spans.get(index) {
+            Some(span) if span.range.contains(&offset) => self.directives.get(index),
+            _ => None,
+        }
+    }
+
+    /// Convert into a format which owns all of its strings.
+    pub fn into_owned(self) -> LogFormat<'static> {
+        LogFormat {
+            directives: self.directives.into_iter().map(Directive::into_owned).collect(),
+            source: Cow::Owned(self.source.into_owned()),
+            spans: self.spans,
+        }
+    }
+
+    /// Convert into a format which can outlive the string it was parsed from, sharing common
+    /// literals and arguments. See `Directive::into_owned_interned`.
+    pub fn into_owned_interned(self) -> LogFormat<'static> {
+        LogFormat {
+            directives: self.directives.into_iter().map(Directive::into_owned_interned).collect(),
+            source: Cow::Owned(self.source.into_owned()),
+            spans: self.spans,
+        }
+    }
+
+    /// Whether any directive in the format requires the module `module`, e.g. `"mod_logio"`.
+    pub fn uses_module(&self, module: &str) -> bool {
+        self.iter().any(|d| d.required_module() == Some(module))
+    }
+
+    /// The directives which may log credentials or other personal data. See
+    /// `Directive::is_potentially_sensitive`.
+    pub fn sensitive_fields(&self) -> Vec<&Directive<'a>> {
+        self.iter().filter(|d| d.is_potentially_sensitive()).collect()
+    }
+
+    /// Whether the format logs the request or response header `name`. Header names are
+    /// case-insensitive, so `"user-agent"` finds `%{User-agent}i`.
+    pub fn contains_header(&self, name: &str) -> bool {
+        self.iter().any(|d| match *d.unconditional() {
+            Directive::ReqHeader(ref h) |
+            Directive::ResHeader(ref h) => h.eq_ignore_ascii_case(name),
+            _ => false,
+        })
+    }
+
+    /// Check the format for likely problems. See `validate`.
+    pub fn validate(&self) -> Vec<FormatWarning> {
+        lint::validate(self)
+    }
+
+    /// Check that log lines written with the format can be split back into their values. See
+    /// `validate_extractable`.
+    pub fn validate_extractable(&self) -> Result<(), Vec<Ambiguity>> {
+        lint::validate_extractable(self)
+    }
+
+    /// Rename every request or response header and trailer called `from`, compared
+    /// case-insensitively, to `to`. Useful for normalizing header capitalization.
+    pub fn rename_header(&mut self, from: &str, to: &str) {
+        for (index, directive) in self.directives.iter_mut().enumerate() {
+            match *unconditional_mut(directive) {
+                Directive::ReqHeader(ref mut name) |
+                Directive::ResHeader(ref mut name) |
+                Directive::ReqTrailer(ref mut name) |
+                Directive::ResTrailer(ref mut name) if name.eq_ignore_ascii_case(from) => {
+                    *name = Cow::Owned(to.to_owned());
+                    rewritten(&mut self.spans, index);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Replace the names of headers, trailers, cookies, notes and environment variables with
+    /// generic placeholders such as `HEADER`, so the format can be shared without revealing them.
+    /// Custom time formats and all other directives and literals are kept.
+    pub fn anonymize(&mut self) {
+        for (index, directive) in self.directives.iter_mut().enumerate() {
+            let (name, placeholder) = match *unconditional_mut(directive) {
+                Directive::ReqHeader(ref mut name) |
+                Directive::ResHeader(ref mut name) => (name, "HEADER"),
+                Directive::ReqTrailer(ref mut name) |
+                Directive::ResTrailer(ref mut name) => (name, "TRAILER"),
+                Directive::Cookie(ref mut name) => (name, "COOKIE"),
+                Directive::Note(ref mut name) => (name, "NOTE"),
+                Directive::EnvVar(ref mut name) => (name, "VAR"),
+                _ => continue,
+            };
+            *name = Cow::Borrowed(placeholder);
+            rewritten(&mut self.spans, index);
+        }
+    }
+
+    /// Collapse every literal made up only of whitespace to a single space. Literals containing
+    /// anything else, such as the quotes around `"%r"`, are left untouched.
+    pub fn normalize_whitespace(&mut self) {
+        for (index, directive) in self.directives.iter_mut().enumerate() {
+            if let Directive::Literal(ref mut text) = *directive {
+                if !text.is_empty() && text.chars().all(char::is_whitespace) && *text != " " {
+                    *text = Cow::Borrowed(" ");
+                    rewritten(&mut self.spans, index);
+                }
+            }
+        }
+    }
+
+    /// Rewrite every request duration directive, `%D`, `%T` or `%{UNIT}T`, to log in `unit`. See
+    /// `Directive::with_time_unit`. Directives which can't be converted are left unchanged and
+    /// reported as `FormatWarning::UnconvertibleTiming`.
+    pub fn normalize_timing_to(&mut self, unit: TimeUnit) -> Vec<FormatWarning> {
+        let mut warnings = Vec::new();
+        for (index, directive) in self.directives.iter_mut().enumerate() {
+            if directive.timing_base_unit().is_none() {
+                continue;
+            }
+            match directive.with_time_unit(&unit) {
+                Some(converted) => {
+                    *directive = converted;
+                    rewritten(&mut self.spans, index);
+                }
+                None => warnings.push(FormatWarning::UnconvertibleTiming(index)),
+            }
+        }
+        warnings
+    }
+
+    /// Whether the format logs the full request target, as the `%U%q` idiom: the URL path
+    /// immediately followed by the query string. See `join_uri`.
+    pub fn has_full_uri(&self) -> bool {
+        self.windows(2).any(|pair| pair[0] == Directive::Path && pair[1] == Directive::Query)
+    }
+
+    /// The directives which log a field already logged earlier in the format. Each field is
+    /// listed once, by its first repeat.
+    ///
+    /// Fields are compared by `field_name`, so `%s` and `%>s` are not duplicates of each other:
+    /// they log the original and final status, which differ for redirected requests.
+    pub fn duplicate_fields(&self) -> Vec<&Directive<'a>> {
+        let mut seen = Vec::new();
+        let mut duplicates: Vec<&Directive<'a>> = Vec::new();
+        for directive in self.iter() {
+            let name = match directive.field_name() {
+                Some(name) => name,
+                None => continue,
+            };
+            if !seen.contains(&name) {
+                seen.push(name);
+            } else if !duplicates.iter().any(|d| d.field_name().as_ref() == Some(&name)) {
+                duplicates.push(directive);
+            }
+        }
+        duplicates
+    }
+
+    /// One directive for each field the format logs, by its first occurrence, with fields
+    /// compared by `base_field`. Unlike `duplicate_fields`, this treats `%s` and `%>s` as the same
+    /// status field.
+    pub fn grouped_fields(&self) -> Vec<&Directive<'a>> {
+        let mut seen = Vec::new();
+        let mut fields = Vec::new();
+        for directive in self.iter() {
+            if let Some(name) = directive.base_field() {
+                if !seen.contains(&name) {
+                    seen.push(name);
+                    fields.push(directive);
+                }
+            }
+        }
+        fields
+    }
+
+    /// A format logging the same directives as this one, in the order their fields appear in
+    /// `template` and separated by single spaces. Directives whose `field_name` isn't in
+    /// `template` follow in their original order.
+    ///
+    /// All literals are dropped, including quotes, so the result is meant for lining up columns
+    /// rather than for logging.
+    pub fn reorder_to(&self, template: &LogFormat) -> LogFormat<'a> {
+        let mut remaining: Vec<&Directive<'a>> =
+            self.iter().filter(|d| !matches!(d, Directive::Literal(_))).collect();
+        let mut ordered = Vec::with_capacity(remaining.len());
+        for field in template.iter().filter_map(Directive::field_name) {
+            let found = remaining.iter().position(|d| d.field_name().as_ref() == Some(&field));
+            if let Some(i) = found {
+                ordered.push(remaining.remove(i));
+            }
+        }
+        ordered.extend(remaining);
+        let mut directives = Storage::with_capacity(ordered.len() * 2);
+        for (index, directive) in ordered.into_iter().enumerate() {
+            if index > 0 {
+                directives.push(Directive::Literal(Cow::Borrowed(" ")));
+            }
+            directives.push(directive.clone());
+        }
+        LogFormat::new(directives)
+    }
+
+    /// Whether two formats log the same fields with the same separators, ignoring differences
+    /// which don't change the log lines. Runs of whitespace in literals are compared as a single
+    /// space, header names case-insensitively, and explicit forms such as `%{canonical}p` equal
+    /// the implicit `%p`.
+    pub fn semantically_eq(&self, other: &LogFormat) -> bool {
+        let (a, b) = (semantic_form(self), semantic_form(other));
+        a.len() == b.len() && a.iter().zip(&b).all(|(a, b)| named::equivalent(a, b))
+    }
+
+    /// The directives of the named format `target` which this format doesn't log, in the order
+    /// they appear in `target`. For example, CLF is missing the referer and user agent headers of
+    /// the Combined Log Format.
+    pub fn missing_for(&self, target: NamedFormat) -> Vec<Directive<'static>> {
+        target.directives()
+            .into_iter()
+            .filter(|d| d.field_name().is_some())
+            .filter(|d| !self.iter().any(|own| named::equivalent(own, d)))
+            .collect()
+    }
+
+    /// Generate a regular expression matching log lines written with this format. See
+    /// `to_regex`.
+    pub fn to_regex(&self) -> String {
+        extract::to_regex(self)
+    }
+
+    /// Generate a regular expression for Python's `re` module matching log lines written with
+    /// this format. See `to_python_regex`.
+    pub fn to_python_regex(&self) -> String {
+        extract::to_python_regex(self)
+    }
+
+    /// Generate a grok pattern matching log lines written with this format. See `to_grok`.
+    pub fn to_grok(&self) -> String {
+        extract::to_grok(self)
+    }
+
+    /// Generate a regular expression matching the start of a log line written with this format.
+    /// See `line_start_pattern`.
+    pub fn line_start_pattern(&self) -> String {
+        extract::line_start_pattern(self)
+    }
+
+    /// Split a log line written with this format into the values of its directives, honoring
+    /// quoted and bracketed values. See `split_fields`. A record written over several lines, by
+    /// a format with newlines in its literals, is split as a whole.
+    pub fn split_quoted_fields<'l>(&self, line: &'l str) -> Option<Vec<&'l str>> {
+        extract::split_fields(self, line)
+    }
+
+    /// Build an extractor for log lines written with this format.
+    #[cfg(feature = "regex")]
+    pub fn extractor(&self) -> Result<Extractor, regex::Error> {
+        Extractor::new(self)
+    }
+
+    /// Render a log line in this format, taking values from `source`. See `render`.
+    pub fn render<S: LogSource + ?Sized>(&self, source: &S) -> String {
+        render::render(self, source)
+    }
+
+    /// Render a log line in this format with the given options. See `render_with`.
+    pub fn render_with<S: LogSource + ?Sized>(&self,
+                                              source: &S,
+                                              options: &RenderOptions)
+                                              -> String {
+        render::render_with(self, source, options)
+    }
+
+    /// An httpd.conf `LogFormat` line defining this format, with an optional nickname, e.g.
+    /// `LogFormat "%h %l %u %t \"%r\" %>s %b" common`.
+    pub fn to_config_line(&self, nickname: Option<&str>) -> String {
+        let mut line = format!("LogFormat {}", config::quote(&self.to_string()));
+        if let Some(nickname) = nickname {
+            line.push(' ');
+            line.push_str(nickname);
+        }
+        line
+    }
+
+    /// ClickHouse column definitions for the fields of this format. See `clickhouse_schema`.
+    pub fn clickhouse_schema(&self) -> String {
+        schema::clickhouse_schema(self)
+    }
+
+    /// A table explaining each directive of the format, one per line: how it's written, its
+    /// field name and its description, padded into aligned columns. Literals are left out.
+    pub fn to_pretty_string(&self) -> String {
+        let rows: Vec<_> = self.iter()
+            .filter_map(|d| d.field_name().map(|name| (d.to_string(), name, d.description())))
+            .collect();
+        let syntax_width = rows.iter().map(|r| r.0.chars().count()).max().unwrap_or(0);
+        let name_width = rows.iter().map(|r| r.1.chars().count()).max().unwrap_or(0);
+        let mut table = String::new();
+        for (syntax, name, description) in rows {
+            table.push_str(&format!("{:<sw$}  {:<nw$}  {}\n",
+                                    syntax,
+                                    name,
+                                    description,
+                                    sw = syntax_width,
+                                    nw = name_width));
+        }
+        table
+    }
+
+    /// The format as it's written by `Display`, colored with ANSI escape codes for a terminal by
+    /// each directive's `kind`. Literals are dimmed.
+    #[cfg(feature = "color")]
+    pub fn to_colored_string(&self) -> String {
+        let mut colored = String::new();
+        for (index, directive) in self.iter().enumerate() {
+            let s = self.spelling(index);
+            let s = match directive.kind() {
+                DirectiveKind::Literal => s.dimmed().to_string(),
+                DirectiveKind::Connection => s.cyan().to_string(),
+                DirectiveKind::Request => s.green().to_string(),
+                DirectiveKind::Response => s.yellow().to_string(),
+                DirectiveKind::Server => s.magenta().to_string(),
+                DirectiveKind::Unknown => s.red().to_string(),
+            };
+            colored.push_str(&s);
+        }
+        colored
+    }
+
+    /// A realistic log line in this format, for documentation or tests. See `sample_line`.
+    pub fn sample_line(&self) -> String {
+        render::sample_line(self)
+    }
+
+    /// Estimate the length of a line logged with this format, for pre-sizing buffers.
+    pub fn estimate_line_width(&self) -> usize {
+        render::estimate_line_width(self)
+    }
+
+    /// Encode the format in a compact binary form, suitable for caching parsed formats.
+    ///
+    /// This is a version byte followed by each directive as encoded by `Directive::to_compact`,
+    /// so directives without an argument take a single byte. Formats encoded by earlier versions
+    /// of the crate can still be decoded.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        binary::encode(self)
+    }
+
+    /// Decode a format previously encoded with `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<LogFormat<'static>, DecodeError> {
+        binary::decode(bytes).map(LogFormat::from)
+    }
+}
+
+/// The directives of `format` with adjacent literals merged and runs of whitespace in them
+/// collapsed to a single space, so formats can be compared by meaning.
+fn semantic_form<'d>(format: &'d [Directive]) -> Vec<Directive<'d>> {
+    let mut form: Vec<Directive<'d>> = Vec::with_capacity(format.len());
+    for directive in format {
+        let literal = match *directive {
+            Directive::Literal(ref literal) => literal,
+            _ => {
+                form.push(directive.clone());
+                continue;
+            }
+        };
+        if !matches!(form.last(), Some(Directive::Literal(_))) {
+            form.push(Directive::Literal(Cow::Owned(String::new())));
+        }
+        if let Some(Directive::Literal(merged)) = form.last_mut() {
+            let merged = merged.to_mut();
+            for c in literal.chars() {
+                if !c.is_whitespace() {
+                    merged.push(c);
+                } else if !merged.ends_with(' ') {
+                    merged.push(' ');
+                }
+            }
+        }
+    }
+    form
+}
+
+impl<'a> From<Vec<Directive<'a>>> for LogFormat<'a> {
+    fn from(directives: Vec<Directive<'a>>) -> LogFormat<'a> {
+        LogFormat::new(directives.into_iter().collect())
+    }
+}
+
+impl<'a> Deref for LogFormat<'a> {
+    type Target = [Directive<'a>];
+
+    fn deref(&self) -> &[Directive<'a>] {
+        &self.directives
+    }
+}
+
+/// Formats are equal when they have the same directives, each written the same way, so `%p` and
+/// `%{canonical}p` are different formats. See `LogFormat::semantically_eq` to compare what they
+/// log.
+impl<'a> PartialEq for LogFormat<'a> {
+    fn eq(&self, other: &LogFormat<'a>) -> bool {
+        self.directives == other.directives &&
+        (0..self.len()).all(|index| match (self.verbatim(index), other.verbatim(index)) {
+            (None, None) => true,
+            _ => self.spelling(index) == other.spelling(index),
+        })
+    }
+}
+
+/// Formats as a log format string, which parses back to the same directives. Directives which
+/// haven't changed since the format was parsed are written as they were spelled.
+impl<'a> fmt::Display for LogFormat<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, directive) in self.iter().enumerate() {
+            match self.verbatim(index) {
+                Some(text) => f.write_str(text)?,
+                None => write!(f, "{}", directive)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for LogFormat<'static> {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        LogFormat::parse(s).map(LogFormat::into_owned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use directive::{Directive, PIDType, PortType};
+    use {CLF, COMBINED};
+
+    #[test]
+    fn test_log_format_parse() {
+        let format = LogFormat::parse("%h %{Referer}i").unwrap();
+        assert_eq!(&format[..],
+                   &[Directive::Hostname,
+                     Directive::Literal(Cow::from(" ")),
+                     Directive::ReqHeader(Cow::from("Referer"))]);
+    }
+
+    #[test]
+    fn test_log_format_directive_at() {
+        let format = LogFormat::parse(CLF).unwrap();
+        assert_eq!(format.directive_at(0), Some(&Directive::Hostname));
+        assert_eq!(format.directive_at(1), Some(&Directive::Hostname));
+        assert_eq!(format.directive_at(2), Some(&Directive::Literal(Cow::from(" "))));
+        assert_eq!(format.directive_at(3), Some(&Directive::Logname));
+        assert_eq!(format.directive_at(CLF.len() - 1), Some(&Directive::ResSize));
+        assert_eq!(format.directive_at(CLF.len()), None);
+        // Offsets are into the string as written, even where a directive displays differently.
+        let format = LogFormat::parse("%{}t %h").unwrap();
+        assert_eq!(format.to_string(), "%{}t %h");
+        assert_eq!(format.directive_at(3), Some(&Directive::ReqRecvTime));
+        assert_eq!(format.directive_at(4), Some(&Directive::Literal(Cow::from(" "))));
+        assert_eq!(format.directive_at(5), Some(&Directive::Hostname));
+        let options = ParseOptions { trim: true, ..ParseOptions::default() };
+        let format = LogFormat::parse_with("  %h\\t%u ", &options).unwrap();
+        assert_eq!(format.directive_at(1), None);
+        assert_eq!(format.directive_at(2), Some(&Directive::Hostname));
+        assert_eq!(format.directive_at(5), Some(&Directive::Literal(Cow::from("\t"))));
+        assert_eq!(format.directive_at(6), Some(&Directive::User));
+        assert_eq!(format.directive_at(8), None);
+        assert_eq!(LogFormat::from(vec![Directive::Hostname]).directive_at(0), None);
+    }
+
+    #[test]
+    fn test_log_format_from_field_names() {
+        let format = LogFormat::from_field_names(&["hostname", "status", "response_size"]).unwrap();
+        assert_eq!(format.to_string(), "%h %>s %b");
+        let format = LogFormat::from_field_names(&["time", "header:Referer", "canonical_port",
+                                                   "request_duration_us", "cookie:id"])
+            .unwrap();
+        assert_eq!(format.to_string(), "%t %{Referer}i %p %D %{id}C");
+        for directive in Directive::all_niladic() {
+            let name = directive.field_name().unwrap();
+            let format = LogFormat::from_field_names(&[&name]).unwrap();
+            assert_eq!(format[0].field_name(), Some(name));
+        }
+        assert_eq!(LogFormat::from_field_names(&["hostname", "nope"]),
+                   Err(UnknownField(String::from("nope"))));
+        assert_eq!(LogFormat::from_field_names(&["trailer:Expires"]),
+                   Err(UnknownField(String::from("trailer:Expires"))));
+        assert_eq!(LogFormat::from_field_names(&[]).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_log_format_into_owned_interned() {
+        fn header(format: &LogFormat<'static>, index: usize) -> *const u8 {
+            match format[index] {
+                Directive::ReqHeader(Cow::Borrowed(name)) => name.as_ptr(),
+                ref other => panic!("unexpected {:?}", other),
+            }
+        }
+        let a = LogFormat::parse(&String::from(COMBINED)).unwrap().into_owned_interned();
+        let b = LogFormat::parse(&String::from("%h \"%{Referer}i\" %{X-Custom}i"))
+            .unwrap()
+            .into_owned_interned();
+        assert_eq!(header(&a, 14), header(&b, 2));
+        assert_eq!(a[1], Directive::Literal(Cow::Borrowed(" ")));
+        assert_eq!(a.to_string(), COMBINED);
+        assert!(matches!(b[4], Directive::ReqHeader(Cow::Owned(_))));
+        assert!(matches!(Directive::Note(Cow::from("Referer")).into_owned(),
+                         Directive::Note(Cow::Owned(_))));
+    }
+
+    #[test]
+    fn test_log_format_from_str() {
+        let format: LogFormat<'static> = CLF.parse().unwrap();
+        assert_eq!(format, LogFormat::parse(CLF).unwrap());
+    }
+
+    #[test]
+    fn test_log_format_display() {
+        assert_eq!(LogFormat::parse(COMBINED).unwrap().to_string(), COMBINED);
+        assert_eq!(LogFormat::parse("%{local}p %{tid}P 100%%").unwrap().to_string(),
+                   "%{local}p %{tid}P 100%%");
+    }
+
+    #[test]
+    fn test_log_format_display_port_and_pid_forms() {
+        let format = "%p %{canonical}p %{local}p %{remote}p %P %{pid}P %{tid}P %{hextid}P";
+        assert_eq!(LogFormat::parse(format).unwrap().to_string(), format);
+    }
+
+    #[test]
+    fn test_log_format_keeps_spelling_when_rewritten() {
+        let mut format = LogFormat::parse("%{canonical}p %{pid}P %{user-agent}i").unwrap();
+        assert_eq!(format[0], Directive::Port(PortType::Canonical));
+        assert_eq!(format[2], Directive::PID(PIDType::PID));
+        format.rename_header("User-Agent", "User-Agent");
+        assert_eq!(format.to_string(), "%{canonical}p %{pid}P %{User-Agent}i");
+        assert_eq!(format, LogFormat::parse("%{canonical}p %{pid}P %{User-Agent}i").unwrap());
+        assert_ne!(format, LogFormat::parse("%p %P %{User-Agent}i").unwrap());
+    }
+
+    #[test]
+    fn test_log_format_to_config_line() {
+        let format = LogFormat::parse(COMBINED).unwrap();
+        assert_eq!(format.to_config_line(Some("combined")),
+                   concat!(r#"LogFormat "%h %l %u %t \"%r\" %>s %b "#,
+                           r#"\"%{Referer}i\" \"%{User-agent}i\"" combined"#));
+        assert_eq!(LogFormat::parse("%h").unwrap().to_config_line(None), r#"LogFormat "%h""#);
+    }
+
+    #[test]
+    fn test_log_format_estimate_line_width() {
+        // 127.0.0.1 - frank [10/Oct/2000:13:55:36 -0700] "GET /apache_pb.gif HTTP/1.0" 200 2326
+        let width = LogFormat::parse(CLF).unwrap().estimate_line_width();
+        assert!((60..=120).contains(&width), "{}", width);
+    }
+
+    #[test]
+    fn test_log_format_to_pretty_string() {
+        let format = LogFormat::parse(CLF).unwrap();
+        let table = format.to_pretty_string();
+        assert_eq!(table.lines().count(), 7);
+        for name in format.iter().filter_map(|d| d.field_name()) {
+            assert!(table.contains(&*name), "{} missing", name);
+        }
+        assert_eq!(table.lines().next(),
+                   Some("%h   hostname       remote hostname"));
+        assert!(table.contains("\n%>s  status         final status of the request\n"));
+    }
+
+    #[cfg(feature = "color")]
+    #[test]
+    fn test_log_format_to_colored_string() {
+        let colored = LogFormat::parse("%h \"%r\" %>s").unwrap().to_colored_string();
+        assert!(colored.contains("\x1b[36m%h\x1b[39m"));
+        assert!(colored.contains("\x1b[32m%r\x1b[39m"));
+        assert!(colored.contains("\x1b[33m%>s\x1b[39m"));
+        assert!(colored.contains("\x1b[2m \"\x1b[0m"));
+        let colored = LogFormat::parse("%{canonical}p %{pid}P").unwrap().to_colored_string();
+        assert!(colored.contains("\x1b[36m%{canonical}p\x1b[39m"));
+        assert!(colored.contains("\x1b[35m%{pid}P\x1b[39m"));
+    }
+
+    #[test]
+    fn test_log_format_line_start_pattern() {
+        assert_eq!(LogFormat::parse(CLF).unwrap().line_start_pattern(),
+                   r"^(?:[0-9a-fA-F:.]+|\S+)");
+    }
+
+    #[test]
+    fn test_log_format_sample_line() {
+        assert_eq!(LogFormat::parse(CLF).unwrap().sample_line(),
+                   concat!(r#"127.0.0.1 - frank [10/Oct/2000:13:55:36 -0700] "#,
+                           r#""GET /apache_pb.gif HTTP/1.0" 200 2326"#));
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_log_format_sample_line_extracts() {
+        for format in &[CLF, COMBINED] {
+            let format = LogFormat::parse(format).unwrap();
+            let line = format.sample_line();
+            let extractor = format.extractor().unwrap();
+            let record = extractor.parse_line(&line).unwrap();
+            for directive in format.iter() {
+                if let Some(name) = directive.field_name() {
+                    assert_eq!(record.get(&name), Some(&*directive.example_value()));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_log_format_uses_module() {
+        assert!(LogFormat::parse("%h %I %O").unwrap().uses_module("mod_logio"));
+        assert!(!LogFormat::parse("%a %>s %b").unwrap().uses_module("mod_logio"));
+    }
+
+    #[test]
+    fn test_log_format_sensitive_fields() {
+        let format = LogFormat::parse("%h \"%{Authorization}i\" %{session}C %>s").unwrap();
+        assert_eq!(format.sensitive_fields(),
+                   [&Directive::ReqHeader(Cow::from("Authorization")),
+                    &Directive::Cookie(Cow::from("session"))]);
+        assert_eq!(LogFormat::parse(COMBINED).unwrap().sensitive_fields(), [&Directive::User]);
+    }
+
+    #[test]
+    fn test_log_format_rename_header() {
+        let mut format = LogFormat::parse("%{referer}i %{REFERER}^ti %{Host}i").unwrap();
+        format.rename_header("Referer", "Referer");
+        assert_eq!(format.to_string(), "%{Referer}i %{Referer}^ti %{Host}i");
+    }
+
+    #[test]
+    fn test_log_format_anonymize() {
+        let mut format = LogFormat::parse(concat!("%h %{X-Internal-Secret}i \"%r\" %>s ",
+                                                  "%{session}C %{%d/%b/%Y}t %{x}^to"))
+            .unwrap();
+        format.anonymize();
+        assert_eq!(format.to_string(),
+                   "%h %{HEADER}i \"%r\" %>s %{COOKIE}C %{%d/%b/%Y}t %{TRAILER}^to");
+        let mut format = LogFormat::parse("%{a}n %{b}e").unwrap();
+        format.anonymize();
+        assert_eq!(format.to_string(), "%{NOTE}n %{VAR}e");
+    }
+
+    #[test]
+    fn test_log_format_reorder_to() {
+        let clf = LogFormat::parse(CLF).unwrap();
+        let shuffled = LogFormat::parse("%>s \"%r\" %u %{Host}i %t %h %b %l").unwrap();
+        assert_eq!(shuffled.reorder_to(&clf).to_string(), "%h %l %u %t %r %>s %b %{Host}i");
+        assert_eq!(clf.reorder_to(&clf).to_string(), "%h %l %u %t %r %>s %b");
+        let partial = LogFormat::parse("%b %h").unwrap();
+        assert_eq!(partial.reorder_to(&clf).to_string(), "%h %b");
+        assert_eq!(partial.reorder_to(&LogFormat::from(vec![])).to_string(), "%b %h");
+    }
+
+    #[test]
+    fn test_log_format_normalize_whitespace() {
+        let mut format = LogFormat::parse("%h   %l\t%u \" %r\"").unwrap();
+        format.normalize_whitespace();
+        assert_eq!(format.to_string(), "%h %l %u \" %r\"");
+    }
+
+    #[test]
+    fn test_log_format_normalize_timing_to() {
+        let mut format = LogFormat::parse("%D %T %{us}T %{min}T").unwrap();
+        assert_eq!(format.normalize_timing_to(TimeUnit::Milliseconds),
+                   [FormatWarning::UnconvertibleTiming(6)]);
+        assert_eq!(format.to_string(), "%{ms}T %{ms}T %{ms}T %{min}T");
+    }
+
+    #[test]
+    fn test_log_format_has_full_uri() {
+        assert!(LogFormat::parse("%h \"%m %U%q %H\" %>s").unwrap().has_full_uri());
+        assert!(!LogFormat::parse("%h %U %q").unwrap().has_full_uri());
+        assert!(!LogFormat::parse(CLF).unwrap().has_full_uri());
+    }
+
+    #[test]
+    fn test_log_format_duplicate_fields() {
+        let format = LogFormat::parse("%h %{Referer}i %>s %{referer}i %s %{Referer}i %h").unwrap();
+        assert_eq!(format.duplicate_fields(),
+                   [&Directive::ReqHeader(Cow::from("referer")), &Directive::Hostname]);
+        assert!(LogFormat::parse(COMBINED).unwrap().duplicate_fields().is_empty());
+    }
+
+    #[test]
+    fn test_log_format_grouped_fields() {
+        let format = LogFormat::parse("%h %s %>s %D %{ms}T").unwrap();
+        assert_eq!(format.grouped_fields(),
+                   [&Directive::Hostname, &Directive::Status, &Directive::ReqTime]);
+        assert!(format.duplicate_fields().is_empty());
+    }
+
+    #[test]
+    fn test_log_format_semantically_eq() {
+        let format = LogFormat::parse("%v:%p %h [pid %P] %>s").unwrap();
+        let same = ["%v:%{canonical}p %h [pid %{pid}P] %>s", "%v:%p  %h\t[pid %P] %>s"];
+        for other in same.iter() {
+            assert!(format.semantically_eq(&LogFormat::parse(other).unwrap()), "{}", other);
+        }
+        let different = ["%v:%{local}p %h [pid %P] %>s", "%v:%p %h [pid %P] %s", "%v:%p %h%>s"];
+        for other in different.iter() {
+            assert!(!format.semantically_eq(&LogFormat::parse(other).unwrap()), "{}", other);
+        }
+        // `%%` parses as a literal of its own.
+        let merged = LogFormat::from(vec![Directive::Literal(Cow::from("100% ")),
+                                          Directive::Hostname]);
+        assert!(LogFormat::parse("100%% %h").unwrap().semantically_eq(&merged));
+    }
+
+    #[test]
+    fn test_log_format_missing_for() {
+        let format = LogFormat::parse(CLF).unwrap();
+        assert_eq!(format.missing_for(NamedFormat::Combined),
+                   [Directive::ReqHeader(Cow::from("Referer")),
+                    Directive::ReqHeader(Cow::from("User-agent"))]);
+        assert!(format.missing_for(NamedFormat::Common).is_empty());
+        assert!(LogFormat::parse(COMBINED).unwrap().missing_for(NamedFormat::Common).is_empty());
+    }
+
+    #[test]
+    fn test_log_format_render_server_names() {
+        use std::collections::HashMap;
+
+        let format = LogFormat::parse("%v %V").unwrap();
+        let mut source = HashMap::new();
+        source.insert("server_name", "www.example.com");
+        source.insert("canonical_server_name", "example.com");
+        assert_eq!(format.render(&source), "www.example.com example.com");
+        assert!(format.duplicate_fields().is_empty());
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_log_format_extract_server_names() {
+        let format = LogFormat::parse("%v %V").unwrap();
+        let extractor = format.extractor().unwrap();
+        let record = extractor.parse_line("www.example.com example.com").unwrap();
+        assert_eq!(record.get("server_name"), Some("www.example.com"));
+        assert_eq!(record.get("canonical_server_name"), Some("example.com"));
+    }
+
+    #[test]
+    fn test_log_format_bytes_size() {
+        // A version byte, one byte per directive, and a length byte and the text of each literal.
+        let format = LogFormat::parse(CLF).unwrap();
+        let literals: usize = format.iter()
+            .map(|d| match *d {
+                Directive::Literal(ref s) => 1 + s.len(),
+                _ => 0,
+            })
+            .sum();
+        assert_eq!(format.to_bytes().len(), 1 + format.len() + literals);
+        assert!(format.to_bytes().len() < CLF.len() * 2);
+        assert_eq!(LogFormat::from_bytes(&format.to_bytes()), Ok(format.into_owned()));
+    }
+
+    #[test]
+    fn test_log_format_bytes_round_trip() {
+        let options = ParseOptions { lenient: true, ..Default::default() };
+        let format = LogFormat::parse_with(
+            "%h %{c}a %{local}p %{hextid}P \"%{User-agent}i\" %{min}T %z", &options)
+            .unwrap();
+        let bytes = format.to_bytes();
+        assert_eq!(LogFormat::from_bytes(&bytes), Ok(format.into_owned()));
+    }
+}
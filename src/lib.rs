@@ -1,22 +1,66 @@
 // #![feature(test)]
 // extern crate test;
 
+#[cfg(feature = "chrono")]
+extern crate chrono;
 #[macro_use]
 extern crate nom;
+#[cfg(feature = "color")]
+extern crate owo_colors;
+#[cfg(feature = "regex")]
+extern crate regex;
+#[cfg(feature = "smallvec")]
+extern crate smallvec;
+#[cfg(feature = "tracing")]
+extern crate tracing;
 
+mod binary;
+mod config;
 mod directive;
+mod errorlog;
+mod extract;
+mod format;
+mod lint;
+mod named;
 mod parser;
+mod render;
+mod schema;
+#[cfg(feature = "tracing")]
+mod span;
 
 // Predefined log formats
-pub const CLF: &'static str = "%h %l %u %t \"%r\" %>s %b";
-pub use parser::logformat_parser;
-pub use directive::Directive;
+pub const CLF: &str = "%h %l %u %t \"%r\" %>s %b";
+pub const COMBINED: &str = "%h %l %u %t \"%r\" %>s %b \"%{Referer}i\" \"%{User-agent}i\"";
+pub const VHOST_COMBINED: &str =
+    "%v:%p %h %l %u %t \"%r\" %>s %O \"%{Referer}i\" \"%{User-Agent}i\"";
+pub const REFERER: &str = "%{Referer}i -> %U";
+pub const AGENT: &str = "%{User-agent}i";
+pub use parser::{directive_parser, directive_ref_parser, iter_directives, logformat_parser,
+                 parse_bytes, parse_many, parse_str, parse_with, strip_trailing_comment,
+                 CharHandler, DirectiveOffsets, Directives, ParseError, ParseOptions, Parser,
+                 ParserBuilder};
+pub use binary::DecodeError;
+pub use config::{parse_customlog_line, FormatRef, LogTarget};
+pub use directive::{Condition, Directive, DirectiveKind, DirectiveRef, HostValueKind,
+                    HttpVersion, TimeUnit};
+pub use errorlog::{parse_errorlog_format, ErrorLogDirective, ErrorLogItem};
+pub use extract::{line_start_pattern, split_fields, to_grok, to_python_regex, to_regex};
+#[cfg(feature = "regex")]
+pub use extract::{ExtractWarning, Extractor, Record, Records};
+pub use format::{LogFormat, UnknownField};
+pub use lint::{validate, validate_extractable, Ambiguity, FormatWarning};
+pub use named::NamedFormat;
+pub use render::{LogSource, RenderOptions, Timestamp, Transformers, join_uri, render,
+                 render_value, render_with, sample_line};
+pub use schema::{clickhouse_schema, merge_schemas, schema, ColumnSpec, ColumnType};
+#[cfg(feature = "tracing")]
+pub use span::SpanFields;
 
 #[cfg(test)]
 mod tests {
     use std::borrow::Cow;
     use nom::IResult::{Done};
-    use super::{CLF, Directive, logformat_parser};
+    use super::{CLF, COMBINED, Directive, LogFormat, logformat_parser};
 
     #[test]
     fn test_logformat_parser_() {
@@ -38,4 +82,36 @@ mod tests {
                         ]
                      ));
     }
+
+    #[test]
+    fn test_sort_by_field_name() {
+        let format = LogFormat::parse(COMBINED).unwrap();
+        let mut fields: Vec<&Directive> = format.iter()
+            .filter(|d| d.field_name().is_some())
+            .collect();
+        fields.sort_by(|a, b| a.cmp_by_field_name(b));
+        let names: Vec<_> = fields.iter().map(|d| d.field_name().unwrap()).collect();
+        assert_eq!(names,
+                   ["hostname",
+                    "logname",
+                    "req_header_referer",
+                    "req_header_user_agent",
+                    "request_line",
+                    "response_size",
+                    "status",
+                    "time",
+                    "user"]);
+    }
+
+    #[test]
+    fn test_combined_user_agent() {
+        // Apache's own config spells it `User-agent`.
+        assert!(COMBINED.contains("%{User-agent}i"));
+        let format = LogFormat::parse(COMBINED).unwrap();
+        assert!(format.contains_header("User-agent"));
+        assert!(format.contains_header("User-Agent"));
+        assert!(format.contains_header("user-agent"));
+        assert!(format.contains_header("REFERER"));
+        assert!(!format.contains_header("Host"));
+    }
 }
@@ -0,0 +1,173 @@
+//! Checks for log formats which parse, but are likely to produce logs that are hard to process.
+
+use std::fmt;
+
+use directive::Directive;
+use extract::is_quoted;
+
+/// A likely problem with a log format.
+///
+/// Indexes refer to the directive in the format which the warning is about.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormatWarning {
+    /// A directive whose value may contain spaces, such as `%r`, `%f` or a header, which isn't
+    /// enclosed in `"` literals, so its value can't be told apart from the following fields.
+    UnquotedField(usize),
+    /// A request duration directive which couldn't be rewritten to log in another unit, because
+    /// it or the target unit is a `TimeUnit::Custom` one.
+    UnconvertibleTiming(usize),
+    /// A `%V`, which logs either the configured server name or the client supplied `Host`
+    /// header, depending on the `UseCanonicalName` setting.
+    UseCanonicalNameDependent(usize),
+    /// A `%a` or `%{c}a`. Behind a proxy or load balancer, `%a` logs the proxy's address unless
+    /// mod_remoteip is configured, and with it logs an address taken from a header which is
+    /// only as trustworthy as the proxies listed in `RemoteIPTrustedProxy`.
+    RemoteIpDependent(usize),
+}
+
+impl FormatWarning {
+    /// Whether the warning is only a reminder about the server configuration, rather than a
+    /// problem with the format itself.
+    pub fn is_advisory(&self) -> bool {
+        matches!(*self,
+                 FormatWarning::UseCanonicalNameDependent(_) | FormatWarning::RemoteIpDependent(_))
+    }
+}
+
+impl fmt::Display for FormatWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FormatWarning::UnquotedField(index) => {
+                write!(f, "directive {} may contain spaces but isn't quoted", index)
+            }
+            FormatWarning::UnconvertibleTiming(index) => {
+                write!(f, "directive {} can't be converted to the requested time unit", index)
+            }
+            FormatWarning::UseCanonicalNameDependent(index) => {
+                write!(f, "directive {} depends on the UseCanonicalName setting", index)
+            }
+            FormatWarning::RemoteIpDependent(index) => {
+                write!(f,
+                       "directive {} depends on mod_remoteip and which proxies are trusted",
+                       index)
+            }
+        }
+    }
+}
+
+/// Two directives with no literal text between them, such as the `%h%l` of `%h%l %u`, so where
+/// one value ends and the next begins can't be told from a log line. Gives their indexes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ambiguity {
+    pub first: usize,
+    pub second: usize,
+}
+
+impl fmt::Display for Ambiguity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "directives {} and {} aren't separated", self.first, self.second)
+    }
+}
+
+/// Check that log lines written with `format` can be split back into their values, returning
+/// each pair of adjacent directives without a literal between them if not. Empty literals don't
+/// separate directives.
+pub fn validate_extractable(format: &[Directive]) -> Result<(), Vec<Ambiguity>> {
+    let mut ambiguities = Vec::new();
+    let mut previous = None;
+    for (index, directive) in format.iter().enumerate() {
+        match *directive {
+            Directive::Literal(ref literal) if literal.is_empty() => {}
+            Directive::Literal(_) => previous = None,
+            _ => {
+                if let Some(first) = previous {
+                    ambiguities.push(Ambiguity { first, second: index });
+                }
+                previous = Some(index);
+            }
+        }
+    }
+    if ambiguities.is_empty() { Ok(()) } else { Err(ambiguities) }
+}
+
+/// Whether the values logged by `directive` commonly contain spaces.
+fn may_contain_spaces(directive: &Directive) -> bool {
+    matches!(*directive.unconditional(),
+             Directive::ReqFirstLine | Directive::Filename | Directive::ReqHeader(_) |
+             Directive::ResHeader(_) | Directive::ReqTrailer(_) | Directive::ResTrailer(_))
+}
+
+/// Check `format` for likely problems, returning a warning for each one found.
+pub fn validate(format: &[Directive]) -> Vec<FormatWarning> {
+    let mut warnings = Vec::new();
+    for (index, directive) in format.iter().enumerate() {
+        if may_contain_spaces(directive) && !is_quoted(format, index) {
+            warnings.push(FormatWarning::UnquotedField(index));
+        }
+        match *directive {
+            Directive::CanonicalServerName => {
+                warnings.push(FormatWarning::UseCanonicalNameDependent(index))
+            }
+            Directive::ClientIP | Directive::PeerIP => {
+                warnings.push(FormatWarning::RemoteIpDependent(index))
+            }
+            _ => {}
+        }
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::borrow::Cow;
+
+    use parser::parse_str;
+    use {CLF, COMBINED};
+
+    #[test]
+    fn test_validate_quoted() {
+        assert!(validate(&parse_str(CLF).unwrap()).is_empty());
+        assert!(validate(&parse_str(COMBINED).unwrap()).is_empty());
+    }
+
+    #[test]
+    fn test_validate_extractable() {
+        assert_eq!(validate_extractable(&parse_str(CLF).unwrap()), Ok(()));
+        assert_eq!(validate_extractable(&parse_str(COMBINED).unwrap()), Ok(()));
+        assert_eq!(validate_extractable(&parse_str("%h%l %u%t%r").unwrap()),
+                   Err(vec![Ambiguity { first: 0, second: 1 },
+                            Ambiguity { first: 3, second: 4 },
+                            Ambiguity { first: 4, second: 5 }]));
+        let format = [Directive::Hostname, Directive::Literal(Cow::from("")), Directive::User];
+        assert_eq!(validate_extractable(&format), Err(vec![Ambiguity { first: 0, second: 2 }]));
+    }
+
+    #[test]
+    fn test_validate_unquoted_header() {
+        let format = parse_str("%h %{User-Agent}i \"%r\" %>s").unwrap();
+        assert_eq!(validate(&format), [FormatWarning::UnquotedField(2)]);
+    }
+
+    #[test]
+    fn test_validate_canonical_server_name() {
+        assert_eq!(validate(&parse_str("%v %V").unwrap()),
+                   [FormatWarning::UseCanonicalNameDependent(2)]);
+    }
+
+    #[test]
+    fn test_validate_remote_ip() {
+        let warnings = validate(&parse_str("%a %{c}a %h %A").unwrap());
+        assert_eq!(warnings,
+                   [FormatWarning::RemoteIpDependent(0), FormatWarning::RemoteIpDependent(2)]);
+        assert!(warnings.iter().all(FormatWarning::is_advisory));
+        assert!(!FormatWarning::UnquotedField(0).is_advisory());
+    }
+
+    #[test]
+    fn test_validate_unquoted_filename() {
+        assert_eq!(validate(&parse_str("%h %f %>s").unwrap()), [FormatWarning::UnquotedField(2)]);
+        assert!(validate(&parse_str("%h \"%f\" %>s").unwrap()).is_empty());
+    }
+}
@@ -0,0 +1,116 @@
+use std::convert::TryFrom;
+
+use directive::Directive;
+use parser;
+use {AGENT, CLF, COMBINED, REFERER, VHOST_COMBINED};
+
+/// One of the standard log formats defined in Apache's default configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NamedFormat {
+    /// The Common Log Format, `common`.
+    Common,
+    /// The Combined Log Format, `combined`, which adds the referer and user agent to CLF.
+    Combined,
+    /// The Combined Log Format prefixed with the virtual host and port, `vhost_combined`.
+    VhostCombined,
+    /// The referer and the URL path requested, `referer`.
+    Referer,
+    /// The user agent, `agent`.
+    Agent,
+}
+
+impl NamedFormat {
+    /// Every named format.
+    pub const ALL: [NamedFormat; 5] = [NamedFormat::Common,
+                                       NamedFormat::Combined,
+                                       NamedFormat::VhostCombined,
+                                       NamedFormat::Referer,
+                                       NamedFormat::Agent];
+
+    /// The format string, as in Apache's default configuration.
+    pub fn format_str(&self) -> &'static str {
+        match *self {
+            NamedFormat::Common => CLF,
+            NamedFormat::Combined => COMBINED,
+            NamedFormat::VhostCombined => VHOST_COMBINED,
+            NamedFormat::Referer => REFERER,
+            NamedFormat::Agent => AGENT,
+        }
+    }
+
+    /// The nickname the format is given in Apache's default configuration.
+    pub fn nickname(&self) -> &'static str {
+        match *self {
+            NamedFormat::Common => "common",
+            NamedFormat::Combined => "combined",
+            NamedFormat::VhostCombined => "vhost_combined",
+            NamedFormat::Referer => "referer",
+            NamedFormat::Agent => "agent",
+        }
+    }
+
+    /// The parsed directives of the format.
+    pub fn directives(&self) -> Vec<Directive<'static>> {
+        parser::parse_str(self.format_str()).expect("named formats are valid")
+    }
+}
+
+/// Whether two directives log the same thing. Header names are case-insensitive, so
+/// `%{User-agent}i` and `%{User-Agent}i` are equivalent.
+pub fn equivalent(a: &Directive, b: &Directive) -> bool {
+    match (a, b) {
+        (Directive::ReqHeader(a), Directive::ReqHeader(b)) |
+        (Directive::ResHeader(a), Directive::ResHeader(b)) |
+        (Directive::ReqTrailer(a), Directive::ReqTrailer(b)) |
+        (Directive::ResTrailer(a), Directive::ResTrailer(b)) => a.eq_ignore_ascii_case(b),
+        _ => a == b,
+    }
+}
+
+/// Identifies which named format a sequence of directives is, failing if it is a custom format.
+impl<'d, 'a> TryFrom<&'d [Directive<'a>]> for NamedFormat {
+    type Error = ();
+
+    fn try_from(directives: &'d [Directive<'a>]) -> Result<NamedFormat, ()> {
+        NamedFormat::ALL
+            .iter()
+            .cloned()
+            .find(|named| {
+                let named = named.directives();
+                named.len() == directives.len() &&
+                named.iter().zip(directives).all(|(a, b)| equivalent(a, b))
+            })
+            .ok_or(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::convert::TryFrom;
+
+    use parser::parse_str;
+    use {CLF, VHOST_COMBINED};
+
+    #[test]
+    fn test_named_format_try_from() {
+        let format = parse_str(CLF).unwrap();
+        assert_eq!(NamedFormat::try_from(&format[..]), Ok(NamedFormat::Common));
+        let format = parse_str("%h %l %u %t \"%r\" %>s %b \"%{referer}i\" \"%{User-Agent}i\"")
+            .unwrap();
+        assert_eq!(NamedFormat::try_from(&format[..]), Ok(NamedFormat::Combined));
+        let format = parse_str("%h %l %u %t \"%r\" %>s").unwrap();
+        assert_eq!(NamedFormat::try_from(&format[..]), Err(()));
+        let explicit = VHOST_COMBINED.replace("%p", "%{canonical}p");
+        let format = parse_str(&explicit).unwrap();
+        assert_eq!(NamedFormat::try_from(&format[..]), Ok(NamedFormat::VhostCombined));
+    }
+
+    #[test]
+    fn test_named_format_round_trip() {
+        for named in NamedFormat::ALL.iter() {
+            assert_eq!(NamedFormat::try_from(&named.directives()[..]), Ok(*named));
+        }
+    }
+}
@@ -1,53 +1,122 @@
 use std::str::{self, FromStr, from_utf8};
 use std::borrow::Cow;
-use directive::{Directive, PIDType, PortType};
+use std::error;
+use std::fmt;
+use std::ops::Range;
+use nom::digit;
+use nom::IResult::{Done, Error, Incomplete};
+use directive::{Condition, Directive, DirectiveRef, PIDType, PortType};
 
+/// An error encountered while parsing a complete log format string.
+///
+/// Offsets are byte offsets into the format string, pointing at the `%` which starts the
+/// offending directive.
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    /// A `%{` argument which is never closed by a `}` before the end of the format.
+    UnterminatedBrace(usize),
+    /// A `%` which is not followed by a valid directive.
+    InvalidDirective(usize),
+    /// The format ends with a lone `%`. A literal percent sign must be written as `%%`.
+    TrailingPercent(usize),
+    /// A `%{VARNAME}C` cookie name which isn't a valid token, as required by
+    /// [RFC 6265](https://tools.ietf.org/html/rfc6265#section-4.1.1).
+    InvalidCookieName(usize),
+    /// Bytes which aren't valid UTF-8, when parsing with `parse_bytes`.
+    InvalidUtf8(usize),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::UnterminatedBrace(offset) => {
+                write!(f, "unterminated '{{' in directive at offset {}", offset)
+            }
+            ParseError::InvalidDirective(offset) => {
+                write!(f, "invalid directive at offset {}", offset)
+            }
+            ParseError::TrailingPercent(offset) => {
+                write!(f, "format ends with an unescaped '%' at offset {}", offset)
+            }
+            ParseError::InvalidCookieName(offset) => {
+                write!(f, "invalid cookie name in directive at offset {}", offset)
+            }
+            ParseError::InvalidUtf8(offset) => write!(f, "invalid UTF-8 at offset {}", offset),
+        }
+    }
+}
+
+impl error::Error for ParseError {}
+
+// The argument of a `%{...}` directive runs up to the first `}` and is taken verbatim, so a
+// `%` inside it, as in a strftime format, doesn't start another directive. Arguments can't
+// contain a `}`; Apache doesn't support nested braces either.
 named!(parens, delimited!(char!('{'), is_not!("}"), char!('}')));
 
-named!(peer_ip_parser <Directive>, do_parse!(
-    char!('{') >>
-    char!('c') >>
-    char!('}') >>
-    char!('a') >>
-    (Directive::PeerIP)
+/// The directive logging `directive` for the underlying connection rather than the request, as
+/// written `%{c}X`. Only `%{c}a` is supported by Apache so far. Any other `%{c}X` is
+/// `DirectiveRef::UnknownConnectionChar`, which is only accepted when parsing leniently.
+fn connection_scope(directive: &str) -> Option<DirectiveRef<'static>> {
+    match directive {
+        "a" => Some(DirectiveRef::PeerIP),
+        _ => directive.chars().next().map(DirectiveRef::UnknownConnectionChar),
+    }
+}
+
+named!(connection_scope_parser <DirectiveRef<'a>>, map_opt!(
+    preceded!(tag!("{c}"), take_str!(1)),
+    connection_scope
 ));
 
-named!(req_cookie_parser <Directive>, map!(
+named!(req_cookie_parser <DirectiveRef<'a>>, map!(
     map_res!(
         terminated!(parens, char!('C')),
         str::from_utf8
-    ), |s| Directive::Cookie(Cow::from(s))
+    ), DirectiveRef::Cookie
 ));
 
-named!(env_var_parser <Directive>, map!(
+named!(env_var_parser <DirectiveRef<'a>>, map!(
     map_res!(
         terminated!(parens, char!('e')),
         str::from_utf8
-    ), |s| Directive::EnvVar(Cow::from(s))
+    ), DirectiveRef::EnvVar
 ));
 
-named!(req_header_parser <Directive>, map!(
+named!(req_header_parser <DirectiveRef<'a>>, map!(
     map_res!(
         terminated!(parens, char!('i')),
         str::from_utf8
-    ), |s| Directive::ReqHeader(Cow::from(s))
+    ), DirectiveRef::ReqHeader
 ));
 
-named!(note_parser <Directive>, map!(
+named!(note_parser <DirectiveRef<'a>>, map!(
     map_res!(
         terminated!(parens, char!('n')),
         str::from_utf8
-    ), |s| Directive::Note(Cow::from(s))
+    ), DirectiveRef::Note
 ));
 
-named!(res_header_parser <Directive>, map!(
+named!(res_header_parser <DirectiveRef<'a>>, map!(
     map_res!(
         terminated!(parens, char!('o')),
         str::from_utf8
-    ), |s| Directive::ResHeader(Cow::from(s))
+    ), DirectiveRef::ResHeader
 ));
 
 
+named!(time_format_parser <DirectiveRef<'a>>, map!(
+    map_res!(
+        terminated!(parens, char!('t')),
+        str::from_utf8
+    ), DirectiveRef::ReqRecvTimeFmt
+));
+
+// Apache logs the default `%t` timestamp when the format is empty, so `%{}t` is accepted as
+// `%t` even though `parens` needs at least one character.
+named!(empty_time_format_parser <DirectiveRef<'a>>, map!(
+    tag!("{}t"), |_| DirectiveRef::ReqRecvTime
+));
+
 named!(port_type_parser_c <PortType>, map!(
     tag!("canonical"), |_| PortType::Canonical
 ));
@@ -63,12 +132,12 @@ named!(port_type_parser <PortType>, alt!(
     port_type_parser_r
 ));
 
-named!(custom_port_parser <Directive>, do_parse!(
+named!(custom_port_parser <DirectiveRef<'a>>, do_parse!(
     char!('{') >>
     p: port_type_parser >>
     char!('}') >>
     char!('p') >>
-    (Directive::Port(p))
+    (DirectiveRef::Port(p))
 ));
 
 named!(pid_type_parser_p <PIDType>, map!(
@@ -86,68 +155,421 @@ named!(pid_type_parser <PIDType>, alt!(
     pid_type_parser_h
 ));
 
-named!(custom_pid_parser <Directive>, do_parse!(
+named!(custom_pid_parser <DirectiveRef<'a>>, do_parse!(
     char!('{') >>
     p: pid_type_parser >>
     char!('}') >>
     char!('P') >>
-    (Directive::PID(p))
+    (DirectiveRef::PID(p))
 ));
 
-named!(final_status_parser <Directive>, do_parse!(
+// Any unit is accepted, as some forks add their own; see `TimeUnit::Custom`.
+named!(serve_time_unit_parser <DirectiveRef<'a>>, map!(
+    map_res!(
+        terminated!(parens, char!('T')),
+        str::from_utf8
+    ), DirectiveRef::ReqServeTimeUnit
+));
+
+named!(final_status_parser <DirectiveRef<'a>>, do_parse!(
     char!('>') >>
     char!('s') >>
-    (Directive::FinalStatus)
+    (DirectiveRef::FinalStatus)
 ));
 
-named!(req_trailer_parser <Directive>, map!(
+named!(req_trailer_parser <DirectiveRef<'a>>, map!(
     map_res!(
         terminated!(parens, tag!("^ti")),
         str::from_utf8
-    ), |s| Directive::ReqTrailer(Cow::from(s))
+    ), DirectiveRef::ReqTrailer
 ));
 
-named!(res_trailer_parser <Directive>, map!(
+named!(res_trailer_parser <DirectiveRef<'a>>, map!(
     map_res!(
         terminated!(parens, tag!("^to")),
         str::from_utf8
-    ), |s| Directive::ResTrailer(Cow::from(s))
+    ), DirectiveRef::ResTrailer
 ));
 
-named!(pub directive_parser <Directive>,
-    preceded!(char!('%'), alt!(
-        peer_ip_parser |
+named!(ssl_var_parser <DirectiveRef<'a>>, map!(
+    map_res!(
+        terminated!(parens, char!('x')),
+        str::from_utf8
+    ), DirectiveRef::SslVar
+));
+
+named!(directive_body <DirectiveRef<'a>>, alt!(
+        connection_scope_parser |
         req_cookie_parser |
         env_var_parser |
         req_header_parser |
         note_parser |
         res_header_parser |
+        time_format_parser |
+        empty_time_format_parser |
         custom_port_parser |
         custom_pid_parser |
+        serve_time_unit_parser |
         final_status_parser |
         req_trailer_parser |
         res_trailer_parser |
-        map_res!(take_str!(1), Directive::from_str)
-    ))
+        ssl_var_parser |
+        map_res!(take_str!(1), DirectiveRef::from_str)
+));
+
+named!(pub directive_ref_parser<&[u8], DirectiveRef<'_>>,
+    preceded!(char!('%'), directive_body)
 );
 
-named!(constant_parser <Directive>, map!(
+named!(status_codes <Vec<u16>>, separated_nonempty_list!(
+    char!(','),
+    map_res!(map_res!(digit, str::from_utf8), u16::from_str)
+));
+
+// A status condition, as in `%400,501{User-agent}i` or `%!200,304{Referer}i`, comes between the
+// `%` and the rest of the directive, which can be any but `%%`.
+named!(conditional_parser <Directive<'a>>, do_parse!(
+    char!('%') >>
+    negated: opt!(char!('!')) >>
+    codes: status_codes >>
+    directive: map_opt!(directive_body, |directive| match directive {
+        DirectiveRef::Literal(_) => None,
+        directive => Some(Directive::from(directive)),
+    }) >>
+    (Directive::Conditional(Box::new(Condition { codes, negated: negated.is_some(), directive })))
+));
+
+named!(pub directive_parser<&[u8], Directive<'_>>, alt!(
+    complete!(conditional_parser) |
+    map!(directive_ref_parser, Directive::from)
+));
+
+/// Decode the backslash escapes which Apache recognizes in the literal text of a format: `\n`,
+/// `\r`, `\t` and `\\`. As in mod_log_config, a backslash before anything else is kept as it
+/// is, and a literal with escapes is still a single literal.
+fn unescape(literal: &str) -> Cow<'_, str> {
+    if !literal.contains('\\') {
+        return Cow::Borrowed(literal);
+    }
+    let mut decoded = String::with_capacity(literal.len());
+    let mut chars = literal.chars().peekable();
+    while let Some(c) = chars.next() {
+        let escaped = match (c, chars.peek()) {
+            ('\\', Some(&'n')) => '\n',
+            ('\\', Some(&'r')) => '\r',
+            ('\\', Some(&'t')) => '\t',
+            ('\\', Some(&'\\')) => '\\',
+            _ => {
+                decoded.push(c);
+                continue;
+            }
+        };
+        chars.next();
+        decoded.push(escaped);
+    }
+    Cow::Owned(decoded)
+}
+
+named!(constant_parser <Directive<'a>>, map!(
     map_res!(
         is_not!("%"),
         from_utf8
     ),
-    |s| Directive::Literal(Cow::from(s))
+    |s| Directive::Literal(unescape(s))
+));
+
+named!(token_parser <Directive<'a>>, alt!(
+    directive_parser |
+    constant_parser
 ));
 
-named!(pub logformat_parser <Vec<Directive>>,
-    many0!(
-        alt!(
-            directive_parser |
-            constant_parser
-        )
-    )
+named!(pub logformat_parser<&[u8], Vec<Directive<'_>>>,
+    many0!(token_parser)
 );
 
+/// Whether `s` is a token as defined by [RFC 7230](https://tools.ietf.org/html/rfc7230#section-3.2.6),
+/// with no whitespace, control characters or separators.
+fn is_token(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+/// Options controlling how `parse_with` parses a log format string.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ParseOptions {
+    /// Parse `%` followed by a character which isn't a known directive as
+    /// `Directive::UnknownChar`, and likewise `%{c}X` as `Directive::UnknownConnectionChar`,
+    /// rather than failing with `ParseError::InvalidDirective`. Cookie names are also accepted as
+    /// written, even if they aren't valid.
+    pub lenient: bool,
+    /// Ignore whitespace at the start and end of the format string, rather than parsing it as
+    /// literal text. Error offsets still refer to the untrimmed string.
+    pub trim: bool,
+    /// When parsing bytes with `parse_bytes`, replace invalid UTF-8 with U+FFFD rather than
+    /// failing with `ParseError::InvalidUtf8`.
+    pub lossy: bool,
+}
+
+/// Parse a complete log format string.
+///
+/// Unlike `logformat_parser`, the whole of `format` must be consumed, and running out of input
+/// part way through a directive is reported as an error rather than as `Incomplete`.
+pub fn parse_str(format: &str) -> Result<Vec<Directive<'_>>, ParseError> {
+    parse_with(format, &ParseOptions::default())
+}
+
+/// Parse a complete log format string, as `parse_str`, with the given options.
+pub fn parse_with<'a>(format: &'a str,
+                      options: &ParseOptions)
+                      -> Result<Vec<Directive<'a>>, ParseError> {
+    Directives::new(format, options).collect()
+}
+
+/// Parse a complete log format from bytes, such as a config file on a system which doesn't use
+/// UTF-8, with the given options. Also returns whether any invalid UTF-8 was replaced.
+///
+/// Invalid UTF-8 is an error unless `options.lossy` is set, in which case it is replaced and the
+/// result owns its strings. Error offsets then refer to the format with replacements made.
+pub fn parse_bytes<'a>(format: &'a [u8],
+                       options: &ParseOptions)
+                       -> Result<(Vec<Directive<'a>>, bool), ParseError> {
+    match str::from_utf8(format) {
+        Ok(format) => parse_with(format, options).map(|directives| (directives, false)),
+        Err(e) if !options.lossy => Err(ParseError::InvalidUtf8(e.valid_up_to())),
+        Err(_) => {
+            let format = String::from_utf8_lossy(format);
+            let directives = parse_with(&format, options)?;
+            Ok((directives.into_iter().map(Directive::into_owned).collect(), true))
+        }
+    }
+}
+
+/// Parses a directive character registered with `ParserBuilder::register_char`.
+pub type CharHandler = fn(char) -> Directive<'static>;
+
+/// Builds a `Parser` which understands extra, vendor-specific directives.
+#[derive(Debug, Clone, Default)]
+pub struct ParserBuilder {
+    parser: Parser,
+}
+
+impl ParserBuilder {
+    /// A builder with the default options and no extra directives.
+    pub fn new() -> ParserBuilder {
+        ParserBuilder::default()
+    }
+
+    /// Parse with `options` rather than the defaults.
+    pub fn options(mut self, options: ParseOptions) -> ParserBuilder {
+        self.parser.options = options;
+        self
+    }
+
+    /// Parse `%c` by calling `handler` with `c`. Only characters which aren't already directives
+    /// can be registered; registering one again replaces its handler.
+    ///
+    /// As the directives are a closed set, a handler usually returns a generic directive such as
+    /// `Directive::UnknownChar` or `Directive::Note`.
+    pub fn register_char(mut self,
+                         c: char,
+                         handler: CharHandler)
+                         -> ParserBuilder {
+        self.parser.chars.retain(|&(registered, _)| registered != c);
+        self.parser.chars.push((c, handler));
+        self
+    }
+
+    /// Finish building the parser.
+    pub fn build(self) -> Parser {
+        self.parser
+    }
+}
+
+/// A log format parser with extra directives registered by a `ParserBuilder`.
+#[derive(Debug, Clone, Default)]
+pub struct Parser {
+    options: ParseOptions,
+    chars: Vec<(char, CharHandler)>,
+}
+
+impl Parser {
+    /// Parse a complete log format string, as `parse_with`.
+    pub fn parse<'a>(&self, format: &'a str) -> Result<Vec<Directive<'a>>, ParseError> {
+        self.iter(format).collect()
+    }
+
+    /// Lazily parse a complete log format string, as `iter_directives`.
+    pub fn iter<'a>(&self, format: &'a str) -> Directives<'a> {
+        let mut directives = Directives::new(format, &self.options);
+        directives.chars = self.chars.clone();
+        directives
+    }
+}
+
+/// Lazily parse a complete log format string, yielding each directive as it is parsed.
+///
+/// Errors are reported as by `parse_str`, after which iteration stops. Nothing after the
+/// directive most recently yielded has been parsed, so a consumer may stop early without paying
+/// for the rest of the format.
+pub fn iter_directives(format: &str) -> Directives<'_> {
+    Directives::new(format, &ParseOptions::default())
+}
+
+/// An iterator over the directives of a log format string. See `iter_directives`.
+#[derive(Debug, Clone)]
+pub struct Directives<'a> {
+    format: &'a str,
+    input: &'a [u8],
+    options: ParseOptions,
+    chars: Vec<(char, CharHandler)>,
+}
+
+impl<'a> Directives<'a> {
+    fn new(format: &'a str, options: &ParseOptions) -> Directives<'a> {
+        // Offsets are measured from the start of `format`, so only the end is trimmed from it.
+        let (format, input) = if options.trim {
+            (format.trim_end(), format.trim())
+        } else {
+            (format, format)
+        };
+        Directives { format, input: input.as_bytes(), options: *options, chars: Vec::new() }
+    }
+
+    /// The part of the format string which hasn't been parsed yet.
+    pub fn remainder(&self) -> &'a str {
+        &self.format[self.format.len() - self.input.len()..]
+    }
+
+    /// Also yield the byte range each directive was parsed from in the format string.
+    pub fn with_offsets(self) -> DirectiveOffsets<'a> {
+        DirectiveOffsets { directives: self }
+    }
+
+    fn parse_next(&mut self) -> Result<Directive<'a>, ParseError> {
+        let input = self.input;
+        let offset = self.format.len() - input.len();
+        match token_parser(input) {
+            Done(rest, directive) => {
+                match *directive.unconditional() {
+                    Directive::Cookie(ref name) if !self.options.lenient && !is_token(name) => {
+                        return Err(ParseError::InvalidCookieName(offset));
+                    }
+                    Directive::UnknownConnectionChar(_) if !self.options.lenient => {
+                        return Err(ParseError::InvalidDirective(offset));
+                    }
+                    _ => {}
+                }
+                self.input = rest;
+                Ok(directive)
+            }
+            Incomplete(_) if input == b"%" => Err(ParseError::TrailingPercent(offset)),
+            Incomplete(_) => {
+                // Only a `%{` with no closing brace anywhere after it is unterminated; anything
+                // else ran out of input looking for the directive character.
+                if input.get(1) == Some(&b'{') && !input.contains(&b'}') {
+                    return Err(ParseError::UnterminatedBrace(offset));
+                }
+                Err(ParseError::InvalidDirective(offset))
+            }
+            Error(_) => {
+                let next = self.format[offset + 1..].chars().next();
+                let handler = self.chars.iter().find(|&&(c, _)| Some(c) == next).map(|&(_, h)| h);
+                match next {
+                    Some(c) if handler.is_some() => {
+                        self.input = &input[1 + c.len_utf8()..];
+                        Ok(handler.unwrap()(c))
+                    }
+                    Some(c) if self.options.lenient && c != '{' => {
+                        self.input = &input[1 + c.len_utf8()..];
+                        Ok(Directive::UnknownChar(c))
+                    }
+                    _ => Err(ParseError::InvalidDirective(offset)),
+                }
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for Directives<'a> {
+    type Item = Result<Directive<'a>, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.input.is_empty() {
+            return None;
+        }
+        let result = self.parse_next();
+        if result.is_err() {
+            // Stop after the first error, rather than retrying the same input forever.
+            self.input = &[];
+        }
+        Some(result)
+    }
+}
+
+/// An iterator over the directives of a log format string and the byte range of the format each
+/// was parsed from. See `Directives::with_offsets`.
+#[derive(Debug, Clone)]
+pub struct DirectiveOffsets<'a> {
+    directives: Directives<'a>,
+}
+
+impl<'a> Iterator for DirectiveOffsets<'a> {
+    type Item = Result<(Directive<'a>, Range<usize>), ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.directives.format.len() - self.directives.input.len();
+        let directive = self.directives.next()?;
+        let end = self.directives.format.len() - self.directives.input.len();
+        Some(directive.map(|directive| (directive, start..end)))
+    }
+}
+
+/// Parse each line of `input` as an independent log format string, skipping blank lines.
+///
+/// Every format is parsed, so one invalid line doesn't prevent the rest from being checked.
+pub fn parse_many(input: &str) -> Vec<Result<Vec<Directive<'_>>, ParseError>> {
+    input.lines().filter(|line| !line.trim().is_empty()).map(parse_str).collect()
+}
+
+/// Strip a trailing `# comment`, and the whitespace before it, from a format string.
+///
+/// Apache doesn't support comments in format strings; this is for tooling which lets formats be
+/// annotated inline. A comment starts at a `#` which begins the string or follows whitespace and
+/// isn't followed by any directive, so a `#` in the middle of a format is kept as a literal, while
+/// a comment may mention a `%` which isn't a directive. A `#` escaped as `\#` never starts a
+/// comment, and the escape is left in place.
+pub fn strip_trailing_comment(format: &str) -> &str {
+    let mut chars = format.char_indices();
+    let mut after_whitespace = true;
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+                after_whitespace = false;
+                continue;
+            }
+            '#' if after_whitespace && !contains_directive(&format[i..]) => {
+                return format[..i].trim_end();
+            }
+            _ => {}
+        }
+        after_whitespace = c.is_whitespace();
+    }
+    format
+}
+
+/// Whether `text` contains a directive, other than the `%%` escape.
+fn contains_directive(text: &str) -> bool {
+    let mut rest = text.as_bytes();
+    while let Some(start) = rest.iter().position(|&b| b == b'%') {
+        rest = &rest[start..];
+        match directive_parser(rest) {
+            Done(_, Directive::Literal(_)) => rest = &rest[2..],
+            Done(..) => return true,
+            _ => rest = &rest[1..],
+        }
+    }
+    false
+}
 
 #[cfg(test)]
 mod tests {
@@ -160,7 +582,8 @@ mod tests {
     use nom::IResult::{Done, Error, Incomplete};
     use nom::Needed::Size;
 
-    use directive::{Directive, PortType, PIDType};
+    use directive::{Directive, DirectiveRef, PortType, PIDType, TimeUnit};
+    use CLF;
 
     #[test]
     fn test_parens_parser() {
@@ -188,6 +611,15 @@ mod tests {
         }
     );
 
+    #[test]
+    fn test_directive_ref_parser() {
+        assert_eq!(directive_ref_parser(b"%{Referer}i"),
+                   Done(&b""[..], DirectiveRef::ReqHeader("Referer")));
+        assert_eq!(directive_ref_parser(b"%{tid}P"),
+                   Done(&b""[..], DirectiveRef::PID(PIDType::TID)));
+        assert_eq!(directive_ref_parser(b"%%"), Done(&b""[..], DirectiveRef::Literal("%")));
+    }
+
     #[test]
     fn test_directive_parser() {
         assert_directive!(b"%a", Directive::ClientIP);
@@ -204,6 +636,24 @@ mod tests {
     #[test]
     fn test_directive_parser_underlying_ip() {
         assert_directive!(b"%{c}a", Directive::PeerIP);
+        assert_eq!(connection_scope_parser(b"{c}a%h"), Done(&b"%h"[..], DirectiveRef::PeerIP));
+        assert_eq!(parse_str("%{c}a %a"),
+                   Ok(vec![Directive::PeerIP, Directive::Literal(Cow::from(" ")),
+                           Directive::ClientIP]));
+        assert_eq!(Directive::PeerIP.to_string(), "%{c}a");
+    }
+    #[test]
+    fn test_directive_parser_unknown_connection_scope() {
+        assert_eq!(connection_scope_parser(b"{c}h"),
+                   Done(&b""[..], DirectiveRef::UnknownConnectionChar('h')));
+        assert_eq!(parse_str("%h %{c}h"), Err(ParseError::InvalidDirective(3)));
+        let options = ParseOptions { lenient: true, ..Default::default() };
+        assert_eq!(parse_with("%{c}h %h", &options),
+                   Ok(vec![Directive::UnknownConnectionChar('h'),
+                           Directive::Literal(Cow::from(" ")),
+                           Directive::Hostname]));
+        assert_eq!(Directive::UnknownConnectionChar('h').to_string(), "%{c}h");
+        assert_eq!(parse_str("%{c}"), Err(ParseError::InvalidDirective(0)));
     }
     #[test]
     fn test_directive_parser_local_ip() {
@@ -316,18 +766,45 @@ mod tests {
         assert_directive!(b"%t", Directive::ReqRecvTime);
     }
     #[test]
-    #[ignore]
     fn test_directive_parser_custom_time() {
-        assert_directive!(b"%{grault}t", Directive::ReqRecvTime);
+        assert_directive!(b"%{grault}t", Directive::ReqRecvTimeFmt(Cow::from("grault")));
+        assert_directive!(b"%{%d/%b/%Y}t", Directive::ReqRecvTimeFmt(Cow::from("%d/%b/%Y")));
+    }
+    #[test]
+    fn test_directive_parser_empty_custom_time() {
+        assert_directive!(b"%{}t", Directive::ReqRecvTime);
+        let format = parse_str("%h %{}t").unwrap();
+        assert_eq!(format, [Directive::Hostname, Directive::Literal(Cow::from(" ")),
+                            Directive::ReqRecvTime]);
+        assert_eq!(parse_str(&format.iter().map(Directive::to_string).collect::<String>()),
+                   Ok(format.clone()));
+        assert_eq!(parse_str("%{}i"), Err(ParseError::InvalidDirective(0)));
+    }
+    #[test]
+    fn test_directive_parser_custom_time_percent() {
+        assert_directive!(b"%{%Y%%m}t", Directive::ReqRecvTimeFmt(Cow::from("%Y%%m")));
+        assert_eq!(parse_str("%{%Y%%m}t %h"),
+                   Ok(vec![Directive::ReqRecvTimeFmt(Cow::from("%Y%%m")),
+                           Directive::Literal(Cow::from(" ")),
+                           Directive::Hostname]));
     }
     #[test]
     fn test_directive_parser_time_to_serve() {
         assert_directive!(b"%T", Directive::ReqServeTime);
     }
     #[test]
-    #[ignore]
+    fn test_directive_parser_time_to_serve_unit() {
+        assert_directive!(b"%{ms}T", Directive::ReqServeTimeUnit(TimeUnit::Milliseconds));
+        assert_directive!(b"%{us}T", Directive::ReqServeTimeUnit(TimeUnit::Microseconds));
+        assert_directive!(b"%{s}T", Directive::ReqServeTimeUnit(TimeUnit::Seconds));
+        assert_directive!(b"%{MS}T", Directive::ReqServeTimeUnit(TimeUnit::Milliseconds));
+        assert_directive!(b"%{min}T",
+                          Directive::ReqServeTimeUnit(TimeUnit::Custom(Cow::from("min"))));
+    }
+    #[test]
     fn test_directive_parser_custom_time_to_serve() {
-        assert_directive!(b"%{garply}T", Directive::ReqServeTime);
+        assert_directive!(b"%{garply}T",
+                          Directive::ReqServeTimeUnit(TimeUnit::Custom(Cow::from("garply"))));
     }
     #[test]
     fn test_directive_parser_user() {
@@ -369,17 +846,260 @@ mod tests {
     fn test_directive_parser_res_trailer_line() {
         assert_directive!(b"%{fred}^to", Directive::ResTrailer(Cow::from("fred")));
     }
+    #[test]
+    fn test_directive_parser_ssl_var() {
+        assert_directive!(b"%{SSL_PROTOCOL}x", Directive::SslVar(Cow::from("SSL_PROTOCOL")));
+    }
+    #[test]
+    fn test_directive_parser_trailer_whitespace() {
+        // `^ti` and `^to` must immediately follow the closing brace, with no space in them.
+        for format in &["%{x} ^ti", "%{x}^ ti", "%{x} ^to", "%{x}^ to", "%h %{x}^t i"] {
+            let offset = format.rfind('%').unwrap();
+            assert_eq!(parse_str(format), Err(ParseError::InvalidDirective(offset)), "{}", format);
+        }
+        let lenient = ParseOptions { lenient: true, ..Default::default() };
+        assert!(parse_with("%{x} ^ti", &lenient).is_err());
+    }
 
     // #[bench]
     // fn bench_directive_parser(b: &mut Bencher) {
     //     b.iter(|| directive_parser(b"%S"));
     // }
 
+    #[test]
+    fn test_parse_str() {
+        assert_eq!(parse_str("%h %{Foo}i"),
+                   Ok(vec![Directive::Hostname,
+                           Directive::Literal(Cow::from(" ")),
+                           Directive::ReqHeader(Cow::from("Foo"))]));
+    }
+
+    #[test]
+    fn test_parse_str_literal_boundary() {
+        // A literal runs up to the next `%` as a single directive, whatever it contains, and
+        // escapes within it are decoded without splitting it.
+        assert_eq!(parse_str("foo\tbar%h"),
+                   Ok(vec![Directive::Literal(Cow::from("foo\tbar")), Directive::Hostname]));
+        assert_eq!(parse_str(r"foo\tbar%h"),
+                   Ok(vec![Directive::Literal(Cow::from("foo\tbar")), Directive::Hostname]));
+        assert_eq!(parse_str(r"a\r\n\\b\qc\"),
+                   Ok(vec![Directive::Literal(Cow::from("a\r\n\\b\\qc\\"))]));
+        assert_eq!(parse_str("%h\\%%\t"),
+                   Ok(vec![Directive::Hostname,
+                           Directive::Literal(Cow::from("\\")),
+                           Directive::Literal(Cow::from("%")),
+                           Directive::Literal(Cow::from("\t"))]));
+    }
+
+    #[test]
+    fn test_parse_str_trailing_digits() {
+        // Status conditions such as `%400s` come before the directive character, so digits after
+        // it are always literal text.
+        assert_eq!(parse_str("%s200"),
+                   Ok(vec![Directive::Status, Directive::Literal(Cow::from("200"))]));
+        assert_eq!(parse_str("%>s404 %h1"),
+                   Ok(vec![Directive::FinalStatus,
+                           Directive::Literal(Cow::from("404 ")),
+                           Directive::Hostname,
+                           Directive::Literal(Cow::from("1"))]));
+    }
+
+    #[test]
+    fn test_parse_str_status_condition() {
+        let format = parse_str("%400,501{User-agent}i %!200{Referer}i").unwrap();
+        assert_eq!(format.len(), 3);
+        assert_eq!(format[0].condition_codes(), Some((&[400, 501][..], false)));
+        assert_eq!(*format[0].unconditional(), Directive::ReqHeader(Cow::from("User-agent")));
+        assert_eq!(format[2].condition_codes(), Some((&[200][..], true)));
+        assert_eq!(*format[2].unconditional(), Directive::ReqHeader(Cow::from("Referer")));
+        assert_eq!(format[0].to_string(), "%400,501{User-agent}i");
+        assert_eq!(format[2].to_string(), "%!200{Referer}i");
+        assert_eq!(parse_str("%404>s").unwrap()[0].condition_codes(), Some((&[404][..], false)));
+        assert_eq!(Directive::Hostname.condition_codes(), None);
+        // A condition needs a directive to apply to.
+        assert_eq!(parse_str("%200%%"), Err(ParseError::InvalidDirective(0)));
+        assert_eq!(parse_str("%!200"), Err(ParseError::InvalidDirective(0)));
+        assert_eq!(parse_str("%200{a b}C"), Err(ParseError::InvalidCookieName(0)));
+    }
+
+    #[test]
+    fn test_parser_register_char() {
+        let parser = ParserBuilder::new()
+            .register_char('z', |_| Directive::Note(Cow::Borrowed("vendor-z")))
+            .register_char('Y', Directive::UnknownChar)
+            .build();
+        assert_eq!(parser.parse("%h %z %Y %h"),
+                   Ok(vec![Directive::Hostname,
+                           Directive::Literal(Cow::from(" ")),
+                           Directive::Note(Cow::from("vendor-z")),
+                           Directive::Literal(Cow::from(" ")),
+                           Directive::UnknownChar('Y'),
+                           Directive::Literal(Cow::from(" ")),
+                           Directive::Hostname]));
+        // Built-in directives can't be overridden, and other unknown characters are still errors.
+        let parser = ParserBuilder::new().register_char('h', |_| Directive::User).build();
+        assert_eq!(parser.parse("%h"), Ok(vec![Directive::Hostname]));
+        assert_eq!(parser.parse("%h %z"), Err(ParseError::InvalidDirective(3)));
+        assert_eq!(parse_str("%z"), Err(ParseError::InvalidDirective(0)));
+    }
+
+    #[test]
+    fn test_parse_str_unterminated_brace() {
+        assert_eq!(parse_str("%{Foo"), Err(ParseError::UnterminatedBrace(0)));
+        assert_eq!(parse_str("%h %{Foo"), Err(ParseError::UnterminatedBrace(3)));
+    }
+
+    #[test]
+    fn test_parse_str_terminated_brace() {
+        // The brace is closed, but there is no directive character after it.
+        assert_eq!(parse_str("%{Foo}"), Err(ParseError::InvalidDirective(0)));
+        assert_eq!(parse_str("%{Foo}i"), Ok(vec![Directive::ReqHeader(Cow::from("Foo"))]));
+    }
+
+    #[test]
+    fn test_parse_str_trailing_percent() {
+        assert_eq!(parse_str("%h %"), Err(ParseError::TrailingPercent(3)));
+        assert_eq!(parse_str("%"), Err(ParseError::TrailingPercent(0)));
+        assert_eq!(parse_str("%h %%"),
+                   Ok(vec![Directive::Hostname,
+                           Directive::Literal(Cow::from(" ")),
+                           Directive::Literal(Cow::from("%"))]));
+    }
+
+    #[test]
+    fn test_parse_str_invalid_directive() {
+        assert_eq!(parse_str("%h %z"), Err(ParseError::InvalidDirective(3)));
+    }
+
+    #[test]
+    fn test_parse_with_lenient() {
+        let lenient = ParseOptions { lenient: true, ..Default::default() };
+        assert_eq!(parse_with("%h %z", &ParseOptions::default()),
+                   Err(ParseError::InvalidDirective(3)));
+        assert_eq!(parse_with("%h %z", &lenient),
+                   Ok(vec![Directive::Hostname,
+                           Directive::Literal(Cow::from(" ")),
+                           Directive::UnknownChar('z')]));
+        assert_eq!(parse_with("%\u{e9}%h", &lenient),
+                   Ok(vec![Directive::UnknownChar('\u{e9}'), Directive::Hostname]));
+        // Only single character directives are preserved.
+        assert_eq!(parse_with("%{Foo}z", &lenient), Err(ParseError::InvalidDirective(0)));
+    }
+
+    #[test]
+    fn test_parse_str_cookie_name() {
+        assert_eq!(parse_str("%{session_id}C"),
+                   Ok(vec![Directive::Cookie(Cow::from("session_id"))]));
+        assert_eq!(parse_str("%h %{session id}C"), Err(ParseError::InvalidCookieName(3)));
+        assert_eq!(parse_str("%{a\tb}C"), Err(ParseError::InvalidCookieName(0)));
+        let lenient = ParseOptions { lenient: true, ..Default::default() };
+        assert_eq!(parse_with("%{session id}C", &lenient),
+                   Ok(vec![Directive::Cookie(Cow::from("session id"))]));
+    }
+
+    #[test]
+    fn test_parse_bytes() {
+        let lossy = ParseOptions { lossy: true, ..Default::default() };
+        assert_eq!(parse_bytes(b"%h \xff%u", &ParseOptions::default()),
+                   Err(ParseError::InvalidUtf8(3)));
+        assert_eq!(parse_bytes(b"%h \xff%u", &lossy),
+                   Ok((vec![Directive::Hostname,
+                            Directive::Literal(Cow::from(" \u{fffd}")),
+                            Directive::User],
+                       true)));
+        assert_eq!(parse_bytes(b"%h", &lossy), Ok((vec![Directive::Hostname], false)));
+    }
+
+    #[test]
+    fn test_parse_with_trim() {
+        let trim = ParseOptions { trim: true, ..Default::default() };
+        assert_eq!(parse_with("  %h  ", &trim), Ok(vec![Directive::Hostname]));
+        assert_eq!(parse_str("  %h  "),
+                   Ok(vec![Directive::Literal(Cow::from("  ")),
+                           Directive::Hostname,
+                           Directive::Literal(Cow::from("  "))]));
+        assert_eq!(parse_with("  %z ", &trim), Err(ParseError::InvalidDirective(2)));
+        assert_eq!(parse_with(" \t\n", &trim), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_iter_directives() {
+        let mut format = String::from("%h");
+        for _ in 0..1000 {
+            format.push_str(" %u");
+        }
+        format.push_str(" %z");
+        let mut directives = iter_directives(&format);
+        assert_eq!(directives.next(), Some(Ok(Directive::Hostname)));
+        assert_eq!(directives.remainder().len(), format.len() - 2);
+        assert_eq!(parse_str(&format), Err(ParseError::InvalidDirective(format.len() - 2)));
+    }
+
+    #[test]
+    fn test_iter_directives_with_offsets() {
+        let mut directives = iter_directives(CLF).with_offsets();
+        assert_eq!(directives.next(), Some(Ok((Directive::Hostname, 0..2))));
+        assert_eq!(directives.next(), Some(Ok((Directive::Literal(Cow::from(" ")), 2..3))));
+        assert_eq!(directives.last(), Some(Ok((Directive::ResSize, CLF.len() - 2..CLF.len()))));
+        let options = ParseOptions { trim: true, ..Default::default() };
+        let mut directives = Directives::new(" %h %z", &options).with_offsets();
+        assert_eq!(directives.next(), Some(Ok((Directive::Hostname, 1..3))));
+        assert_eq!(directives.nth(1), Some(Err(ParseError::InvalidDirective(4))));
+    }
+
+    #[test]
+    fn test_iter_directives_stops_after_error() {
+        let mut directives = iter_directives("%h%z%u");
+        assert_eq!(directives.next(), Some(Ok(Directive::Hostname)));
+        assert_eq!(directives.next(), Some(Err(ParseError::InvalidDirective(2))));
+        assert_eq!(directives.next(), None);
+    }
+
+    #[test]
+    fn test_parse_many() {
+        assert_eq!(parse_many("%h %u\n\n%h %z\r\n%>s\n"),
+                   vec![Ok(vec![Directive::Hostname,
+                                Directive::Literal(Cow::from(" ")),
+                                Directive::User]),
+                        Err(ParseError::InvalidDirective(3)),
+                        Ok(vec![Directive::FinalStatus])]);
+    }
+
+    #[test]
+    fn test_strip_trailing_comment() {
+        assert_eq!(strip_trailing_comment("%h %u # client and user"), "%h %u");
+        assert_eq!(strip_trailing_comment("# only a comment"), "");
+        assert_eq!(strip_trailing_comment("%h #%p %u"), "%h #%p %u");
+        assert_eq!(strip_trailing_comment("%h # %u"), "%h # %u");
+        assert_eq!(strip_trailing_comment("%h order#1"), "%h order#1");
+        assert_eq!(strip_trailing_comment("%h \\# not a comment"), "%h \\# not a comment");
+        assert_eq!(strip_trailing_comment("%h \\#1 # tagged"), "%h \\#1");
+        assert_eq!(strip_trailing_comment("%h # 50% sampled, 100%% kept"), "%h");
+        assert_eq!(strip_trailing_comment("%h # sampled with %u"), "%h # sampled with %u");
+        assert_eq!(strip_trailing_comment("%h # %!200{Referer}i"), "%h # %!200{Referer}i");
+        assert_eq!(strip_trailing_comment(CLF), CLF);
+    }
+
     #[test]
     fn test_logformat_parser_single() {
         assert_eq!(logformat_parser(b"%a"), Done(&b""[..], vec![Directive::ClientIP]));
     }
 
+    #[test]
+    fn test_logformat_parser_time_only() {
+        let time = Directive::ReqRecvTimeFmt(Cow::from("%Y-%m-%dT%H:%M:%S"));
+        assert_eq!(logformat_parser(b"%{%Y-%m-%dT%H:%M:%S}t"),
+                   Done(&b""[..], vec![time.clone()]));
+        assert_eq!(parse_str("%{%Y-%m-%dT%H:%M:%S}t").unwrap().iter()
+                       .filter_map(Directive::field_name)
+                       .collect::<Vec<_>>(),
+                   ["time__y__m__dt_h__m__s"]);
+        assert_eq!(logformat_parser(b"%t%{%Y-%m-%dT%H:%M:%S}t%{msec_frac}t"),
+                   Done(&b""[..], vec![Directive::ReqRecvTime,
+                                       time,
+                                       Directive::ReqRecvTimeFmt(Cow::from("msec_frac"))]));
+    }
+
     #[test]
     fn test_logformat_parser_multiple() {
         assert_eq!(logformat_parser(b"%a%a%a%a%a%a"),
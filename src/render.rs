@@ -0,0 +1,593 @@
+use std::borrow::{Borrow, Cow};
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, FixedOffset, Offset, TimeZone, Utc};
+#[cfg(feature = "chrono")]
+use chrono::format::{Item, StrftimeItems};
+
+use directive::{Directive, PIDType};
+
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct",
+                            "Nov", "Dec"];
+
+/// A point in time, along with the offset from UTC it should be displayed in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timestamp {
+    /// The point in time.
+    pub time: SystemTime,
+    /// The offset from UTC, in seconds east of Greenwich.
+    pub utc_offset: i32,
+}
+
+/// Convert a count of days since 1970-01-01 to a `(year, month, day)` civil date.
+///
+/// See Howard Hinnant's [chrono-Compatible Low-Level Date Algorithms](http://howardhinnant.github.io/date_algorithms.html#civil_from_days).
+fn civil_from_days(days: i64) -> (i64, usize, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month as usize, day)
+}
+
+impl Timestamp {
+    /// A timestamp at `time`, displayed `utc_offset` seconds east of UTC.
+    pub fn new(time: SystemTime, utc_offset: i32) -> Timestamp {
+        Timestamp { time, utc_offset }
+    }
+
+    /// Whole seconds since the Unix epoch, in local time.
+    fn local_seconds(&self) -> i64 {
+        let seconds = match self.time.duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_secs() as i64,
+            // Round times before the epoch down to the previous whole second.
+            Err(e) => {
+                let d = e.duration();
+                -(d.as_secs() as i64) - if d.subsec_nanos() > 0 { 1 } else { 0 }
+            }
+        };
+        seconds + i64::from(self.utc_offset)
+    }
+
+    /// Format the timestamp as one of the `%{format}t` formats Apache handles itself rather than
+    /// with `strftime`: `sec`, `msec` and `usec` since the epoch, and `msec_frac` and
+    /// `usec_frac`, the fraction of the second. Returns `None` for any other format.
+    fn epoch_format(&self, format: &str) -> Option<String> {
+        let format = format.trim_start_matches("begin:").trim_start_matches("end:");
+        let micros = match self.time.duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_micros() as i128,
+            Err(e) => -(e.duration().as_micros() as i128),
+        };
+        Some(match format {
+            "sec" => micros.div_euclid(1_000_000).to_string(),
+            "msec" => micros.div_euclid(1_000).to_string(),
+            "usec" => micros.to_string(),
+            "msec_frac" => format!("{:03}", micros.rem_euclid(1_000_000) / 1_000),
+            "usec_frac" => format!("{:06}", micros.rem_euclid(1_000_000)),
+            _ => return None,
+        })
+    }
+
+    /// Format the timestamp with a `strftime(3)` format string, as used by `%{format}t`, or as
+    /// one of the `sec`, `msec`, `usec`, `msec_frac` and `usec_frac` formats Apache handles itself.
+    ///
+    /// A leading `begin:` or `end:` is ignored. Returns `None` if the format string is invalid.
+    #[cfg(feature = "chrono")]
+    pub fn format(&self, format: &str) -> Option<String> {
+        if let Some(formatted) = self.epoch_format(format) {
+            return Some(formatted);
+        }
+        let format = format.trim_start_matches("begin:").trim_start_matches("end:");
+        let items: Vec<Item> = StrftimeItems::new(format).collect();
+        if items.contains(&Item::Error) {
+            return None;
+        }
+        let offset = FixedOffset::east_opt(self.utc_offset)?;
+        let time = DateTime::<Utc>::from(self.time).with_timezone(&offset);
+        Some(time.format_with_items(items.into_iter()).to_string())
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl<Tz: TimeZone> From<DateTime<Tz>> for Timestamp {
+    fn from(time: DateTime<Tz>) -> Timestamp {
+        let utc_offset = time.offset().fix().local_minus_utc();
+        Timestamp { time: SystemTime::from(time), utc_offset }
+    }
+}
+
+/// Formats as Apache's default `%t` time, e.g. `[18/Sep/2011:19:18:28 -0400]`.
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let seconds = self.local_seconds();
+        let (year, month, day) = civil_from_days(seconds.div_euclid(86_400));
+        let time = seconds.rem_euclid(86_400);
+        let sign = if self.utc_offset < 0 { '-' } else { '+' };
+        let offset = self.utc_offset.abs() / 60;
+        write!(f,
+               "[{:02}/{}/{:04}:{:02}:{:02}:{:02} {}{:02}{:02}]",
+               day,
+               MONTHS[month - 1],
+               year,
+               time / 3600,
+               time / 60 % 60,
+               time % 60,
+               sign,
+               offset / 60,
+               offset % 60)
+    }
+}
+
+/// A source of values for rendering log lines.
+pub trait LogSource {
+    /// The raw value to log for `directive`, or `None` if it isn't available.
+    fn value(&self, directive: &Directive) -> Option<Cow<'_, str>>;
+
+    /// The time the request was received. When provided this is used to render `%t`, rather
+    /// than a pre-formatted value.
+    fn request_time(&self) -> Option<Timestamp> {
+        None
+    }
+}
+
+/// A map from field name (see `Directive::field_name`) to value.
+impl<K, V, S> LogSource for HashMap<K, V, S>
+    where K: Borrow<str> + Hash + Eq,
+          V: AsRef<str>,
+          S: BuildHasher
+{
+    fn value(&self, directive: &Directive) -> Option<Cow<'_, str>> {
+        let name = directive.field_name()?;
+        self.get(name.as_ref()).map(|v| Cow::Borrowed(v.as_ref()))
+    }
+}
+
+/// Format a raw value the way Apache logs it for `directive`.
+///
+/// Hex thread IDs are given as decimal and written as lowercase hex, without a `0x` prefix.
+/// Empty values are written as `-` where Apache would (see `Directive::clf_dash_on_empty`), or
+/// as `0` for other numeric directives such as `%k`. Values are otherwise written as given.
+///
+/// Values aren't validated: one which isn't a number, such as `abc` for `%s` or a hex thread
+/// ID, is written as given too, so rendering never fails.
+pub fn render_value<'r>(directive: &Directive, raw: &'r str) -> Cow<'r, str> {
+    if raw.is_empty() {
+        return Cow::Borrowed(empty_value(directive, "-"));
+    }
+    match *directive {
+        Directive::PID(PIDType::HexTID) => {
+            match raw.parse::<u64>() {
+                Ok(id) => Cow::Owned(format!("{:x}", id)),
+                Err(_) => Cow::Borrowed(raw),
+            }
+        }
+        _ => Cow::Borrowed(raw),
+    }
+}
+
+/// Join a `%U` URL path and `%q` query string into a request target. The query string should
+/// include its leading `?`, as `%q` logs it, and may be empty.
+pub fn join_uri(path: &str, query: &str) -> String {
+    let mut uri = String::with_capacity(path.len() + query.len() + 1);
+    uri.push_str(path);
+    if !query.is_empty() && !query.starts_with('?') {
+        uri.push('?');
+    }
+    uri.push_str(query);
+    uri
+}
+
+/// The value logged for `directive` when it has none, using `missing` in place of Apache's `-`.
+fn empty_value<'m>(directive: &Directive, missing: &'m str) -> &'m str {
+    if directive.clf_dash_on_empty() {
+        missing
+    } else if directive.is_numeric() {
+        "0"
+    } else {
+        ""
+    }
+}
+
+/// Render a time directive from the source's request time, if it has one, displayed in
+/// `utc_offset` when that's given rather than the source's own offset.
+///
+/// Without the `chrono` feature only the `sec`, `msec`, `usec`, `msec_frac` and `usec_frac`
+/// formats of `%{format}t` are rendered from the request time. Other formats are taken from the
+/// source's pre-formatted value instead.
+fn render_time<S: LogSource + ?Sized>(directive: &Directive,
+                                      source: &S,
+                                      utc_offset: Option<i32>)
+                                      -> Option<String> {
+    let time = || {
+        source.request_time().map(|time| match utc_offset {
+            Some(utc_offset) => Timestamp { utc_offset, ..time },
+            None => time,
+        })
+    };
+    match *directive {
+        Directive::ReqRecvTime => time().map(|t| t.to_string()),
+        #[cfg(feature = "chrono")]
+        Directive::ReqRecvTimeFmt(ref format) => time()?.format(format),
+        #[cfg(not(feature = "chrono"))]
+        Directive::ReqRecvTimeFmt(ref format) => time()?.epoch_format(format),
+        _ => None,
+    }
+}
+
+/// Derive a value the source doesn't provide from others it does. `%S` is the sum of `%I` and
+/// `%O`, so it's derived when both of those are available as whole numbers.
+fn derived_value<S: LogSource + ?Sized>(directive: &Directive, source: &S) -> Option<String> {
+    match *directive {
+        Directive::Size => {
+            let received = source.value(&Directive::SizeReceived)?.parse::<u64>().ok()?;
+            let sent = source.value(&Directive::SizeSent)?.parse::<u64>().ok()?;
+            received.checked_add(sent).map(|size| size.to_string())
+        }
+        _ => None,
+    }
+}
+
+/// Estimate the length of a line logged with `format`, from the lengths of each directive's
+/// `example_value`.
+pub fn estimate_line_width(format: &[Directive]) -> usize {
+    format.iter().map(|d| d.example_value().len()).sum()
+}
+
+/// A realistic log line for `format`, made up of each directive's `example_value`.
+pub fn sample_line(format: &[Directive]) -> String {
+    let mut line = String::with_capacity(estimate_line_width(format));
+    for directive in format {
+        line.push_str(&directive.example_value());
+    }
+    line
+}
+
+/// A function transforming a value before it's logged.
+type Transform = Box<dyn Fn(&str) -> String>;
+
+/// Functions transforming the values of fields before they're logged, keyed by the field's
+/// `Directive::field_name`, e.g. to anonymize client addresses without changing the source.
+#[derive(Default)]
+pub struct Transformers {
+    transformers: HashMap<String, Transform>,
+}
+
+impl Transformers {
+    /// An empty set of transformers.
+    pub fn new() -> Transformers {
+        Transformers::default()
+    }
+
+    /// Transform the values of the field called `field_name` with `transform`, replacing any
+    /// transformer previously registered for it.
+    pub fn insert<F>(&mut self, field_name: &str, transform: F)
+        where F: Fn(&str) -> String + 'static
+    {
+        self.transformers.insert(field_name.to_string(), Box::new(transform));
+    }
+
+    /// Transform `raw`, the value of `directive`, if a transformer is registered for its field.
+    fn apply<'r>(&self, directive: &Directive, raw: &'r str) -> Cow<'r, str> {
+        let transform = directive.field_name().and_then(|name| self.transformers.get(&*name));
+        match transform {
+            Some(transform) => Cow::Owned(transform(raw)),
+            None => Cow::Borrowed(raw),
+        }
+    }
+}
+
+impl fmt::Debug for Transformers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.transformers.keys()).finish()
+    }
+}
+
+/// Options controlling how `render_with` renders log lines.
+#[derive(Debug, Clone, Copy)]
+pub struct RenderOptions<'a> {
+    /// The placeholder written for missing values, `-` by default as in Apache.
+    pub missing: &'a str,
+    /// The offset from UTC, in seconds east of Greenwich, to render times in instead of the
+    /// source's own. `Some(0)` renders every time in UTC; `None`, the default, keeps the
+    /// source's offset.
+    pub utc_offset: Option<i32>,
+    /// Transformers applied to the values the source provides, before they're formatted by
+    /// `render_value` and written. Values derived from other fields and placeholders aren't
+    /// transformed.
+    pub transformers: Option<&'a Transformers>,
+}
+
+impl<'a> Default for RenderOptions<'a> {
+    fn default() -> RenderOptions<'a> {
+        RenderOptions {
+            missing: "-",
+            utc_offset: None,
+            transformers: None,
+        }
+    }
+}
+
+/// Render a log line for `format`, taking values from `source`.
+///
+/// Values which the source doesn't provide are logged as `-`, or as `0` or an empty string for
+/// the few directives where Apache does so. The exception is `%S`, which is rendered as the sum
+/// of `%I` and `%O` when the source provides those instead.
+///
+/// A directive with a status condition, such as `%400,501{User-agent}i`, is logged as `-` when
+/// the source's status doesn't meet the condition. As in Apache that's the final status, `%>s`,
+/// falling back to `%s` when the source only provides that. Without either it's always logged.
+pub fn render<S: LogSource + ?Sized>(format: &[Directive], source: &S) -> String {
+    render_with(format, source, &RenderOptions::default())
+}
+
+/// Render a log line, as `render`, with the given options.
+pub fn render_with<S: LogSource + ?Sized>(format: &[Directive],
+                                          source: &S,
+                                          options: &RenderOptions)
+                                          -> String {
+    let mut line = String::with_capacity(estimate_line_width(format));
+    for directive in format {
+        if let Directive::Literal(ref literal) = *directive {
+            line.push_str(literal);
+            continue;
+        }
+        let directive = match *directive {
+            Directive::Conditional(ref condition) => {
+                let status = source.value(&Directive::FinalStatus)
+                    .or_else(|| source.value(&Directive::Status))
+                    .and_then(|status| status.parse().ok());
+                if status.is_some_and(|status| !condition.matches(status)) {
+                    line.push_str(options.missing);
+                    continue;
+                }
+                &condition.directive
+            }
+            ref directive => directive,
+        };
+        if let Some(time) = render_time(directive, source, options.utc_offset) {
+            line.push_str(&time);
+            continue;
+        }
+        match source.value(directive) {
+            Some(ref raw) if !raw.is_empty() => {
+                let raw = match options.transformers {
+                    Some(transformers) => transformers.apply(directive, raw),
+                    None => Cow::Borrowed(&**raw),
+                };
+                line.push_str(&render_value(directive, &raw));
+            }
+            _ => {
+                match derived_value(directive, source) {
+                    Some(value) => line.push_str(&value),
+                    None => line.push_str(empty_value(directive, options.missing)),
+                }
+            }
+        }
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::borrow::Cow;
+    use std::collections::HashMap;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use directive::{Directive, PIDType};
+    use parser::parse_str;
+
+    struct TimeSource(Timestamp);
+
+    impl LogSource for TimeSource {
+        fn value(&self, _: &Directive) -> Option<Cow<'_, str>> {
+            None
+        }
+
+        fn request_time(&self) -> Option<Timestamp> {
+            Some(self.0)
+        }
+    }
+
+    #[test]
+    fn test_render_value_pid() {
+        assert_eq!(render_value(&Directive::PID(PIDType::PID), "4660"), "4660");
+        assert_eq!(render_value(&Directive::PID(PIDType::TID), "4660"), "4660");
+        assert_eq!(render_value(&Directive::PID(PIDType::HexTID), "4660"), "1234");
+        assert_eq!(render_value(&Directive::PID(PIDType::HexTID), "48879"), "beef");
+    }
+
+    #[test]
+    fn test_render_value_malformed_number() {
+        assert_eq!(render_value(&Directive::Status, "abc"), "abc");
+        assert_eq!(render_value(&Directive::PID(PIDType::HexTID), "abc"), "abc");
+        assert_eq!(render_value(&Directive::PID(PIDType::HexTID), "-1"), "-1");
+        assert_eq!(render_value(&Directive::ResSize, "99999999999999999999999"),
+                   "99999999999999999999999");
+
+        let format = [Directive::Status, Directive::Literal(Cow::from(" ")), Directive::Size];
+        let mut source = HashMap::new();
+        source.insert("original_status", "abc");
+        source.insert("bytes_received", "abc");
+        source.insert("bytes_sent", "12");
+        assert_eq!(render(&format, &source), "abc 0");
+    }
+
+    #[test]
+    fn test_render() {
+        let format = [Directive::Hostname,
+                      Directive::Literal(Cow::from(" ")),
+                      Directive::User,
+                      Directive::Literal(Cow::from(" ")),
+                      Directive::PID(PIDType::HexTID)];
+        let mut source = HashMap::new();
+        source.insert("hostname", "127.0.0.1");
+        source.insert("hex_tid", "255");
+        assert_eq!(render(&format, &source), "127.0.0.1 - ff");
+    }
+
+    #[test]
+    fn test_render_conditional() {
+        let format = parse_str("%400,501{User-agent}i %!200{Referer}i").unwrap();
+        let mut source = HashMap::new();
+        source.insert("req_header_user_agent", "curl/7.64.1");
+        source.insert("req_header_referer", "http://example.com/");
+        assert_eq!(render(&format, &source), "curl/7.64.1 http://example.com/");
+        source.insert("status", "200");
+        assert_eq!(render(&format, &source), "- -");
+        source.insert("status", "400");
+        assert_eq!(render(&format, &source), "curl/7.64.1 http://example.com/");
+    }
+
+    #[test]
+    fn test_render_with_missing() {
+        let format = [Directive::User, Directive::Literal(Cow::from(" ")), Directive::KeepAlive];
+        let null = RenderOptions { missing: "NULL", ..RenderOptions::default() };
+        let empty = RenderOptions { missing: "", ..RenderOptions::default() };
+        let mut source = HashMap::new();
+        assert_eq!(render(&format, &source), "- 0");
+        assert_eq!(render_with(&format, &source, &null), "NULL 0");
+        source.insert("user", "");
+        assert_eq!(render_with(&format, &source, &empty), " 0");
+        source.insert("user", "frank");
+        assert_eq!(render_with(&format, &source, &null), "frank 0");
+    }
+
+    #[test]
+    fn test_render_with_transformers() {
+        let format = [Directive::Hostname, Directive::Literal(Cow::from(" ")), Directive::User];
+        let mut transformers = Transformers::new();
+        transformers.insert("hostname", |ip: &str| match ip.rfind('.') {
+            Some(end) => format!("{}.0", &ip[..end]),
+            None => ip.to_string(),
+        });
+        let options = RenderOptions {
+            transformers: Some(&transformers),
+            ..RenderOptions::default()
+        };
+        let mut source = HashMap::new();
+        source.insert("hostname", "192.168.4.27");
+        source.insert("user", "frank");
+        assert_eq!(render_with(&format, &source, &options), "192.168.4.0 frank");
+        assert_eq!(render(&format, &source), "192.168.4.27 frank");
+    }
+
+    #[test]
+    fn test_render_keepalive() {
+        let format = [Directive::KeepAlive];
+        let mut source = HashMap::new();
+        assert_eq!(render(&format, &source), "0");
+        source.insert("keepalive", "0");
+        assert_eq!(render(&format, &source), "0");
+        source.insert("keepalive", "3");
+        assert_eq!(render(&format, &source), "3");
+        assert_eq!(render_value(&Directive::KeepAlive, ""), "0");
+        assert_eq!(render_value(&Directive::User, ""), "-");
+    }
+
+    #[test]
+    fn test_render_size_from_components() {
+        let format = [Directive::SizeReceived,
+                      Directive::Literal(Cow::from(" ")),
+                      Directive::SizeSent,
+                      Directive::Literal(Cow::from(" ")),
+                      Directive::Size];
+        let mut source = HashMap::new();
+        source.insert("bytes_received", "468");
+        source.insert("bytes_sent", "2611");
+        assert_eq!(render(&format, &source), "468 2611 3079");
+        source.insert("bytes_transferred", "3000");
+        assert_eq!(render(&format, &source), "468 2611 3000");
+        source.remove("bytes_transferred");
+        source.insert("bytes_sent", "-");
+        assert_eq!(render(&format, &source), "468 - 0");
+    }
+
+    #[test]
+    fn test_join_uri() {
+        assert_eq!(join_uri("/search", "?q=apache"), "/search?q=apache");
+        assert_eq!(join_uri("/search", "q=apache"), "/search?q=apache");
+        assert_eq!(join_uri("/index.html", ""), "/index.html");
+    }
+
+    #[test]
+    fn test_timestamp_display() {
+        let time = UNIX_EPOCH + Duration::from_secs(1_316_387_908);
+        assert_eq!(Timestamp::new(time, -4 * 3600).to_string(), "[18/Sep/2011:19:18:28 -0400]");
+        assert_eq!(Timestamp::new(time, 0).to_string(), "[18/Sep/2011:23:18:28 +0000]");
+        assert_eq!(Timestamp::new(time, 5 * 3600 + 1800).to_string(),
+                   "[19/Sep/2011:04:48:28 +0530]");
+    }
+
+    #[test]
+    fn test_timestamp_display_before_epoch() {
+        let time = UNIX_EPOCH - Duration::from_millis(500);
+        assert_eq!(Timestamp::new(time, 0).to_string(), "[31/Dec/1969:23:59:59 +0000]");
+    }
+
+    #[test]
+    fn test_render_request_time() {
+        let format = [Directive::Literal(Cow::from("t=")), Directive::ReqRecvTime];
+        let time = UNIX_EPOCH + Duration::from_secs(1_316_387_908);
+        let source = TimeSource(Timestamp::new(time, -4 * 3600));
+        assert_eq!(render(&format, &source), "t=[18/Sep/2011:19:18:28 -0400]");
+    }
+
+    #[test]
+    fn test_render_time_offset_override() {
+        let format = [Directive::ReqRecvTime];
+        let time = UNIX_EPOCH + Duration::from_secs(1_316_387_908);
+        let source = TimeSource(Timestamp::new(time, 2 * 3600));
+        let utc = RenderOptions { utc_offset: Some(0), ..RenderOptions::default() };
+        assert_eq!(render_with(&format, &source, &utc), "[18/Sep/2011:23:18:28 +0000]");
+        let fixed = RenderOptions { utc_offset: Some(-4 * 3600), ..RenderOptions::default() };
+        assert_eq!(render_with(&format, &source, &fixed), "[18/Sep/2011:19:18:28 -0400]");
+        assert_eq!(render(&format, &source), "[19/Sep/2011:01:18:28 +0200]");
+    }
+
+    #[test]
+    fn test_render_epoch_time() {
+        let format = parse_str("%{sec}t %{msec_frac}t %{begin:msec}t %{end:usec_frac}t").unwrap();
+        let time = UNIX_EPOCH + Duration::from_micros(1_316_387_908_042_017);
+        let mut source = TimeSource(Timestamp::new(time, -4 * 3600));
+        assert_eq!(render(&format, &source), "1316387908 042 1316387908042 042017");
+        source.0.time = UNIX_EPOCH - Duration::from_micros(1_500);
+        assert_eq!(render(&format, &source), "-1 998 -2 998500");
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_render_chrono_time() {
+        use chrono::{FixedOffset, TimeZone};
+
+        let time = FixedOffset::west_opt(4 * 3600).unwrap()
+            .with_ymd_and_hms(2011, 9, 18, 19, 18, 28)
+            .unwrap();
+        let source = TimeSource(Timestamp::from(time));
+        let format = [Directive::ReqRecvTimeFmt(Cow::from("%Y-%m-%dT%H:%M:%S%z")),
+                      Directive::Literal(Cow::from(" ")),
+                      Directive::ReqRecvTimeFmt(Cow::from("end:%d/%b/%Y")),
+                      Directive::Literal(Cow::from(" ")),
+                      Directive::ReqRecvTime];
+        assert_eq!(render(&format, &source),
+                   "2011-09-18T19:18:28-0400 18/Sep/2011 [18/Sep/2011:19:18:28 -0400]");
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_timestamp_format_invalid() {
+        let time = Timestamp::new(UNIX_EPOCH, 0);
+        assert_eq!(time.format("%Y"), Some("1970".to_owned()));
+        assert_eq!(time.format("%Q"), None);
+    }
+}
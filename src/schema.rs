@@ -0,0 +1,154 @@
+//! Table schemas for loading extracted log fields into databases.
+
+use std::borrow::Cow;
+
+use directive::{Directive, PIDType};
+
+/// The general type of a column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColumnType {
+    Text,
+    Integer,
+    /// A timestamp in Apache's default `%t` format.
+    Time,
+}
+
+/// A column of a table holding extracted log fields.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnSpec {
+    /// The column name, from `Directive::field_name`.
+    pub name: Cow<'static, str>,
+    pub column_type: ColumnType,
+    /// Whether the column may have no value, because it's missing from some of the formats.
+    pub nullable: bool,
+}
+
+/// The general type of the field logged by `directive`.
+fn column_type(directive: &Directive) -> ColumnType {
+    match *directive {
+        Directive::ReqRecvTime => ColumnType::Time,
+        _ if directive.is_numeric() => ColumnType::Integer,
+        _ => ColumnType::Text,
+    }
+}
+
+/// The columns for the fields of `format`, in format order. A field logged more than once gets a
+/// single column.
+pub fn schema(format: &[Directive]) -> Vec<ColumnSpec> {
+    let mut columns: Vec<ColumnSpec> = Vec::new();
+    for directive in format {
+        let name = match directive.field_name() {
+            Some(name) => name,
+            None => continue,
+        };
+        if !columns.iter().any(|c| c.name == name) {
+            columns.push(ColumnSpec { name, column_type: column_type(directive), nullable: false });
+        }
+    }
+    columns
+}
+
+/// The union of the columns of several formats, so logs written with any of them can be loaded
+/// into one table. Columns are in order of first appearance. A column missing from any of the
+/// formats is nullable, and one with different types in different formats is text.
+pub fn merge_schemas(formats: &[&[Directive]]) -> Vec<ColumnSpec> {
+    let schemas: Vec<_> = formats.iter().map(|format| schema(format)).collect();
+    let mut merged: Vec<ColumnSpec> = Vec::new();
+    for column in schemas.iter().flatten() {
+        match merged.iter_mut().find(|c| c.name == column.name) {
+            Some(existing) => {
+                if existing.column_type != column.column_type {
+                    existing.column_type = ColumnType::Text;
+                }
+            }
+            None => merged.push(column.clone()),
+        }
+    }
+    for column in &mut merged {
+        column.nullable = !schemas.iter().all(|s| s.iter().any(|c| c.name == column.name));
+    }
+    merged
+}
+
+/// The ClickHouse column type for the field logged by `directive`.
+fn clickhouse_type(directive: &Directive) -> &'static str {
+    use directive::Directive::*;
+    match *directive {
+        Status | FinalStatus | Port(_) => "UInt16",
+        PID(PIDType::PID) | PID(PIDType::TID) | KeepAlive => "UInt32",
+        ReqRecvTime => "DateTime",
+        _ if directive.is_numeric() => "UInt64",
+        _ => "String",
+    }
+}
+
+/// ClickHouse column definitions for the fields of `format`, one per line, for use in a
+/// `CREATE TABLE` statement. Columns are named by `Directive::field_name`, and a field logged
+/// more than once gets a single column.
+///
+/// Apache logs `-` for some missing numbers, such as a `%b` of zero, which ClickHouse only
+/// accepts for numeric columns with `input_format_null_as_default` or a `Nullable` type.
+pub fn clickhouse_schema(format: &[Directive]) -> String {
+    let mut names = Vec::new();
+    let mut columns = Vec::new();
+    for directive in format {
+        let name = match directive.field_name() {
+            Some(name) => name,
+            None => continue,
+        };
+        if names.contains(&name) {
+            continue;
+        }
+        columns.push(format!("{} {}", name, clickhouse_type(directive)));
+        names.push(name);
+    }
+    columns.join(",\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use parser::parse_str;
+    use {CLF, COMBINED};
+
+    #[test]
+    fn test_merge_schemas() {
+        let clf = parse_str(CLF).unwrap();
+        let combined = parse_str(COMBINED).unwrap();
+        let merged = merge_schemas(&[&clf, &combined]);
+        assert_eq!(merged.len(), 9);
+        assert_eq!(merged[..7], schema(&clf)[..]);
+        assert_eq!(merged[7],
+                   ColumnSpec {
+                       name: Cow::from("req_header_referer"),
+                       column_type: ColumnType::Text,
+                       nullable: true,
+                   });
+        assert!(merged[8].nullable);
+        assert_eq!(merged[8].name, "req_header_user_agent");
+        assert_eq!(merge_schemas(&[&combined]), schema(&combined));
+
+        let msec = parse_str("%{msec}t %s").unwrap();
+        let merged = merge_schemas(&[&clf, &msec]);
+        let time = merged.iter().find(|c| c.name == "time").unwrap();
+        assert_eq!((time.column_type, time.nullable), (ColumnType::Time, true));
+        let msec = merged.iter().find(|c| c.name == "time_msec").unwrap();
+        assert_eq!((msec.column_type, msec.nullable), (ColumnType::Text, true));
+    }
+
+    #[test]
+    fn test_clickhouse_schema() {
+        assert_eq!(clickhouse_schema(&parse_str(CLF).unwrap()),
+                   "hostname String,\n\
+                    logname String,\n\
+                    user String,\n\
+                    time DateTime,\n\
+                    request_line String,\n\
+                    status UInt16,\n\
+                    response_size UInt64");
+        assert_eq!(clickhouse_schema(&parse_str("%>s %{local}p %D %s %>s").unwrap()),
+                   "status UInt16,\nlocal_port UInt16,\nrequest_duration_us UInt64,\n\
+                    original_status UInt16");
+    }
+}
@@ -0,0 +1,104 @@
+//! Rendering access logs from the fields recorded on a `tracing` span.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+
+use tracing::field::{Field, Visit};
+
+use directive::Directive;
+use render::LogSource;
+
+/// Values recorded on a `tracing` span or event, keyed by field name, for use as a `LogSource`.
+///
+/// Fields are looked up by `Directive::field_name`, so a span created with
+/// `info_span!("request", hostname = "127.0.0.1", status = 200)` provides `%h` and `%>s`.
+/// Record a span's fields into this from a subscriber or layer with `Attributes::record` and
+/// `Record::record`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SpanFields {
+    fields: HashMap<String, String>,
+}
+
+impl SpanFields {
+    /// An empty set of fields.
+    pub fn new() -> SpanFields {
+        SpanFields::default()
+    }
+
+    /// The recorded value of the field `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields.get(name).map(String::as_str)
+    }
+}
+
+impl Visit for SpanFields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.fields.insert(field.name().to_owned(), value.to_owned());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.fields.insert(field.name().to_owned(), format!("{:?}", value));
+    }
+}
+
+impl LogSource for SpanFields {
+    fn value(&self, directive: &Directive) -> Option<Cow<'_, str>> {
+        self.fields.value(directive)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::{Arc, Mutex};
+
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    use format::LogFormat;
+    use CLF;
+
+    /// Records the fields of every new span.
+    struct Recorder(Arc<Mutex<SpanFields>>);
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes) -> Id {
+            span.record(&mut *self.0.lock().unwrap());
+            Id::from_u64(1)
+        }
+
+        fn record(&self, _: &Id, values: &Record) {
+            values.record(&mut *self.0.lock().unwrap());
+        }
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, _: &Event) {}
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[test]
+    fn test_render_span_fields() {
+        let fields = Arc::new(Mutex::new(SpanFields::new()));
+        tracing::subscriber::with_default(Recorder(fields.clone()), || {
+            let span = tracing::info_span!("request",
+                                           hostname = "127.0.0.1",
+                                           request_line = "GET / HTTP/1.1",
+                                           status = tracing::field::Empty);
+            span.record("status", 200);
+        });
+        let fields = fields.lock().unwrap();
+        assert_eq!(fields.get("status"), Some("200"));
+        assert_eq!(LogFormat::parse(CLF).unwrap().render(&*fields),
+                   "127.0.0.1 - - - \"GET / HTTP/1.1\" 200 -");
+    }
+}